@@ -1,19 +1,162 @@
-use crate::command::execute_command;
-use crate::todo::Todo;
-use crate::storage::load_all;
-use chrono::Local;
+use crate::clipboard;
+use crate::command::{default_view, execute_command, Command, Session};
+use crate::stats::tag_counts;
+use crate::config::{Config, Separator};
+use crate::keymap::{Action, Keymap};
+use crate::theme::Theme;
+use crate::todo::{parse_due, quote, Todo, Urgency};
+use crate::storage::{line_count, load_all, TODO_FILE};
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
 use color_eyre::Result;
 use ratatui::{
-    crossterm::event::{self, Event, KeyCode, KeyEventKind},
-    layout::{Constraint, Layout},
-    style::{Color, Modifier, Style, Stylize},
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
+    layout::{Constraint, Layout, Margin, Position, Rect},
+    style::{Modifier, Style, Stylize},
     text::{Line, Span, Text},
-    widgets::{Block, List, ListItem, Paragraph},
+    widgets::{Block, Clear, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState, Wrap},
     DefaultTerminal, Frame,
 };
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
-enum InputMode { Normal, Editing }
+/// 列の見出し (絞り込み中は `表示件数/全件数`)
+fn column_title(name: &str, shown: usize, total: usize, filtered: bool) -> String {
+    if filtered { format!("{} ({}/{})", name, shown, total) } else { format!("{} ({})", name, total) }
+}
+
+/// `view` の位置を `+project` ごとにまとめる (プロジェクト名順で，プロジェクトの無いものは最後の `None`．
+/// 複数のプロジェクトを持つ todo はそれぞれに入る)
+fn project_groups(todos: &[Todo], view: &[usize]) -> Vec<(Option<String>, Vec<usize>)> {
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut unsorted = Vec::new();
+    for (pos, &i) in view.iter().enumerate() {
+        let projects = &todos[i].description.projects;
+        if projects.is_empty() {
+            unsorted.push(pos);
+        }
+        for p in projects {
+            let group = groups.entry(p.clone()).or_default();
+            if group.last() != Some(&pos) {
+                group.push(pos);
+            }
+        }
+    }
+    let mut groups: Vec<_> = groups.into_iter().map(|(p, positions)| (Some(p), positions)).collect();
+    if !unsorted.is_empty() {
+        groups.push((None, unsorted));
+    }
+    groups
+}
+
+/// `todo.txt` の行数が `limit` を超えていれば整理を促す (`limit` が 0 なら何もしない)
+fn size_warning(lines: usize, limit: usize) -> Option<String> {
+    (limit > 0 && lines > limit).then(|| {
+        format!("todo.txt has {} lines (over {}); consider 'clear!' to remove completed tasks", lines, limit)
+    })
+}
+
+/// 起動時にヘッダーに出す案内 (`path` が無ければ作られることを，大きすぎれば整理を促す)
+fn startup_message(path: &Path, max_lines: usize) -> Option<String> {
+    if path.exists() {
+        size_warning(line_count(path), max_lines)
+    } else {
+        Some("No todo.txt found — your first 'add' will create it.".into())
+    }
+}
+
+/// `text` を `query` に一致する部分 (`true`) とそれ以外に分ける
+/// (`fold_case` なら大文字・小文字を区別せずに探し，元の表記のまま返す)
+fn split_matches<'a>(text: &'a str, query: &str, fold_case: bool) -> Vec<(&'a str, bool)> {
+    let fold = |c: char| -> Vec<char> { if fold_case { c.to_lowercase().collect() } else { vec![c] } };
+    let query: Vec<char> = query.chars().flat_map(fold).collect();
+    if query.is_empty() {
+        return vec![(text, false)];
+    }
+    // `start` から一致すれば一致した部分の終わり
+    let match_end = |start: usize| {
+        let mut matched = 0;
+        for (j, c) in text[start..].char_indices() {
+            let folded = fold(c);
+            if !query[matched..].starts_with(&folded) {
+                return None;
+            }
+            matched += folded.len();
+            if matched == query.len() {
+                return Some(start + j + c.len_utf8());
+            }
+        }
+        None
+    };
+    let (mut parts, mut last, mut i) = (Vec::new(), 0, 0);
+    while let Some(c) = text[i..].chars().next() {
+        match match_end(i) {
+            Some(end) => {
+                if last < i {
+                    parts.push((&text[last..i], false));
+                }
+                parts.push((&text[i..end], true));
+                (last, i) = (end, end);
+            }
+            None => i += c.len_utf8(),
+        }
+    }
+    if last < text.len() {
+        parts.push((&text[last..], false));
+    }
+    parts
+}
+
+/// 未保存のまま終了しようとしたときの確認
+const UNSAVED_PROMPT: &str = "Unsaved changes — quit anyway? (y/n)";
+
+enum InputMode { Normal, Editing, Focused, Detail }
+
+/// フォーカス中のブロック
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Focus { Input, Due, NoDue }
+
+/// 件数 `len` の列で `rows` 件ずつ表示するときに有効なスクロール位置に丸める
+fn clamp_offset(offset: usize, len: usize, rows: usize) -> usize {
+    offset.min(len.saturating_sub(rows))
+}
+
+/// 列の高さと 1 件あたりの行数から，同時に表示できる件数を計算 (最低 1 件)
+fn rows_for_height(height: u16, lines_per_task: u16) -> usize {
+    (height.saturating_sub(2) / lines_per_task.max(1)).max(1) as usize
+}
+
+/// 列の 1 行分 (`by-project` ではプロジェクトの見出しも並ぶ)
+#[derive(Debug, Clone, PartialEq)]
+enum Row {
+    /// プロジェクト名 (`None` は "Unsorted") と件数
+    Header(Option<String>, usize),
+    /// `view` 上の位置
+    Task(usize),
+}
+
+/// `projects` / `contexts` の一覧から選ぶメニュー
+struct TagMenu {
+    /// `+work` / `@home` の形のタグと件数
+    tags: Vec<(String, usize)>,
+    selected: usize,
+}
+
+impl TagMenu {
+    /// 選んだタグで絞り込むコマンド
+    fn command(&self) -> String {
+        let tag = &self.tags[self.selected].0;
+        format!("filter {}{}", &tag[..1], quote(&tag[1..]))
+    }
+}
+
+/// Tab 補完で候補を巡回中の状態
+struct Completion {
+    start: usize,
+    candidates: Vec<String>,
+    index: usize,
+}
 
 pub struct App {
     todos: Vec<Todo>,
@@ -22,66 +165,656 @@ pub struct App {
     cursor: usize,
     mode: InputMode,
     error: Option<String>,
+    message: Option<String>,
+    completion: Option<Completion>,
+    focus: Focus,
+    due_scroll: usize,
+    nodue_scroll: usize,
+    /// 各列に同時に表示できる件数 (描画のたびに列の高さから計算)
+    due_rows: usize,
+    nodue_rows: usize,
+    /// フォーカス中の列で選択している項目 (列内の位置)
+    selected: usize,
+    /// 編集中の todo の `view` 上の位置 (新規追加時は `None`)
+    editing_index: Option<usize>,
+    /// 直前の描画での各ブロックの位置 (マウス操作の判定用)
+    input_area: Rect,
+    due_area: Rect,
+    nodue_area: Rect,
+    session: Session,
+    keymap: Keymap,
+    /// 期日選択のカレンダーで選んでいる日 (開いていなければ `None`)
+    picker: Option<NaiveDate>,
+    /// `projects` / `contexts` の後に開くメニュー
+    tag_menu: Option<TagMenu>,
+    /// 一覧選択中に打った移動先の番号
+    jump: String,
+    /// 未保存のまま終了するか確認中
+    confirm_quit: bool,
+    /// `by-project` で畳んでいるプロジェクト (`None` は "Unsorted")
+    collapsed: BTreeSet<Option<String>>,
 }
 
 impl App {
     pub fn new() -> Self {
-        let todos = load_all();
-        let view = (0..todos.len()).collect();
-        Self { todos, view, input: String::new(), cursor: 0, mode: InputMode::Normal, error: None }
+        let todos = load_all(Path::new(TODO_FILE));
+        let config = Config::load();
+        let message = startup_message(Path::new(TODO_FILE), config.max_lines);
+        Self::with_session(todos, Session::new(config), message)
+    }
+
+    /// 一覧を書き込み禁止で表示 (`message` は起動時にヘッダーに出す．`from_stdin` なら todo ファイルを読み直さない)
+    pub fn read_only(todos: Vec<Todo>, message: &str, from_stdin: bool) -> Self {
+        let mut session = Session::new(Config::load());
+        session.read_only = true;
+        session.from_stdin = from_stdin;
+        Self::with_session(todos, session, Some(message.into()))
+    }
+
+    fn with_session(todos: Vec<Todo>, session: Session, message: Option<String>) -> Self {
+        let view = default_view(&todos, &session);
+        Self {
+            todos, view, input: String::new(), cursor: 0, mode: InputMode::Normal, error: None, message, completion: None,
+            focus: Focus::Input, due_scroll: 0, nodue_scroll: 0, due_rows: 1, nodue_rows: 1, selected: 0, editing_index: None,
+            input_area: Rect::default(), due_area: Rect::default(), nodue_area: Rect::default(),
+            session, keymap: Keymap::load(), picker: None, tag_menu: None, jump: String::new(), confirm_quit: false,
+            collapsed: BTreeSet::new(),
+        }
+    }
+
+    /// `view` を due あり / なしの列に振り分け，列ごとの並び順に並べる (`view` 上の位置を返す)
+    fn partition(&self) -> (Vec<usize>, Vec<usize>) {
+        let (mut due, mut nodue): (Vec<usize>, Vec<usize>) =
+            (0..self.view.len()).partition(|&pos| self.todos[self.view[pos]].description.due.is_some());
+        // `when` の区分見出しは `view` の順を前提にしているので並べ替えない
+        if !self.session.grouped {
+            self.session.due_sort.apply(&mut due, &self.todos, &self.view);
+            self.session.nodue_sort.apply(&mut nodue, &self.todos, &self.view);
+            self.completed_last(&mut due);
+            self.completed_last(&mut nodue);
+        }
+        (due, nodue)
+    }
+
+    /// 設定が有効なら完了済みの todo を順序を保ったまま末尾に寄せる
+    fn completed_last(&self, positions: &mut [usize]) {
+        if self.session.config.completed_last {
+            positions.sort_by_key(|&pos| self.todos[self.view[pos]].completion);
+        }
+    }
+
+    /// 画面上のブロックの並び (入力欄，左の列，右の列．`by-project` では列は 1 つ)
+    fn focus_order(&self) -> &'static [Focus] {
+        if self.session.config.by_project {
+            &[Focus::Input, Focus::Due]
+        } else if self.session.config.nodue_left {
+            &[Focus::Input, Focus::NoDue, Focus::Due]
+        } else {
+            &[Focus::Input, Focus::Due, Focus::NoDue]
+        }
+    }
+
+    /// フォーカスを画面上の並びで `delta` 個ずらす (`wrap` なら端で折り返す)
+    fn move_focus(&mut self, delta: isize, wrap: bool) {
+        let order = self.focus_order();
+        let i = order.iter().position(|&b| b == self.focus).unwrap_or(0) as isize + delta;
+        let i = if wrap { i.rem_euclid(order.len() as isize) } else { i.clamp(0, order.len() as isize - 1) };
+        self.focus = order[i as usize];
+    }
+
+    /// 列の `view` 上の位置一覧
+    fn column_positions(&self, block: Focus) -> Vec<usize> {
+        let (due, nodue) = self.partition();
+        match block {
+            Focus::Input => Vec::new(),
+            Focus::Due => due,
+            Focus::NoDue => nodue,
+        }
+    }
+
+    /// 列に並ぶ行 (`by-project` では `Due` の列にプロジェクトごとの見出しと，畳んでいない todo を並べる)
+    fn column_rows(&self, block: Focus) -> Vec<Row> {
+        if !self.session.config.by_project {
+            return self.column_positions(block).into_iter().map(Row::Task).collect();
+        }
+        if block != Focus::Due {
+            return Vec::new();
+        }
+        let mut rows = Vec::new();
+        for (project, mut positions) in project_groups(&self.todos, &self.view) {
+            self.completed_last(&mut positions);
+            let collapsed = self.collapsed.contains(&project);
+            rows.push(Row::Header(project, positions.len()));
+            if !collapsed {
+                rows.extend(positions.into_iter().map(Row::Task));
+            }
+        }
+        rows
+    }
+
+    fn focused_rows(&self) -> Vec<Row> {
+        self.column_rows(self.focus)
+    }
+
+    fn scroll_mut(&mut self, block: Focus) -> Option<&mut usize> {
+        match block {
+            Focus::Input => None,
+            Focus::Due => Some(&mut self.due_scroll),
+            Focus::NoDue => Some(&mut self.nodue_scroll),
+        }
+    }
+
+    fn scroll(&self, block: Focus) -> usize {
+        match block {
+            Focus::Input => 0,
+            Focus::Due => self.due_scroll,
+            Focus::NoDue => self.nodue_scroll,
+        }
+    }
+
+    fn rows(&self, block: Focus) -> usize {
+        match block {
+            Focus::NoDue => self.nodue_rows,
+            _ => self.due_rows,
+        }
+    }
+
+    fn area(&self, block: Focus) -> Rect {
+        match block {
+            Focus::Input => self.input_area,
+            Focus::Due => self.due_area,
+            Focus::NoDue => self.nodue_area,
+        }
+    }
+
+    /// 選択中の todo の `view` 上の位置
+    fn selected_pos(&self) -> Option<usize> {
+        match self.focused_rows().get(self.selected) {
+            Some(&Row::Task(pos)) => Some(pos),
+            _ => None,
+        }
+    }
+
+    /// 選択中の行で Enter: 見出しならそのプロジェクトを畳む / 開き，todo なら詳細を開く
+    fn open_selected(&mut self) {
+        match self.focused_rows().get(self.selected) {
+            Some(Row::Header(project, _)) => {
+                if !self.collapsed.remove(project) {
+                    self.collapsed.insert(project.clone());
+                }
+                self.clamp_scroll();
+            }
+            Some(Row::Task(_)) => self.mode = InputMode::Detail,
+            None => {}
+        }
+    }
+
+    /// 選択を `delta` 件移動し，選択が見える位置までスクロール
+    fn select_by(&mut self, delta: isize) {
+        let len = self.focused_rows().len();
+        self.selected = self.selected.saturating_add_signed(delta).min(len.saturating_sub(1));
+        let (selected, rows) = (self.selected, self.rows(self.focus));
+        if let Some(scroll) = self.scroll_mut(self.focus) {
+            if selected < *scroll {
+                *scroll = selected;
+            } else if selected >= *scroll + rows {
+                *scroll = selected + 1 - rows;
+            }
+        }
+    }
+
+    /// 一覧が変わった後にスクロール位置と選択を範囲内に戻す
+    fn clamp_scroll(&mut self) {
+        let (due, nodue) = (self.column_rows(Focus::Due), self.column_rows(Focus::NoDue));
+        self.due_scroll = clamp_offset(self.due_scroll, due.len(), self.due_rows);
+        self.nodue_scroll = clamp_offset(self.nodue_scroll, nodue.len(), self.nodue_rows);
+        self.selected = self.selected.min(self.focused_rows().len().saturating_sub(1));
+    }
+
+    /// 選択中の todo を入力欄に読み込んで編集を始める
+    fn start_edit(&mut self) {
+        if let Some(pos) = self.selected_pos() {
+            self.input = self.todos[self.view[pos]].format();
+            self.cursor = self.grapheme_count();
+            self.editing_index = Some(pos);
+            self.mode = InputMode::Editing;
+        }
+    }
+
+    /// 入力欄に `prefix` を入れた状態で編集を始める
+    fn start_typing(&mut self, prefix: &str) {
+        self.focus = Focus::Input;
+        self.input = prefix.to_string();
+        self.cursor = self.grapheme_count();
+        self.mode = InputMode::Editing;
+    }
+
+    /// 選択中の todo の行 (コピーする内容)
+    fn selected_text(&self) -> Option<String> {
+        self.selected_pos().map(|pos| self.todos[self.view[pos]].format())
+    }
+
+    /// 選択中の todo を todo.txt の書式でクリップボードにコピー
+    fn copy_selected(&mut self) {
+        let Some(text) = self.selected_text() else { return };
+        match clipboard::copy(&text) {
+            Ok(()) => {
+                self.error = None;
+                self.message = Some(format!("Copied: {}", text));
+            }
+            Err(e) => self.error = Some(format!("Clipboard unavailable: {}", e)),
+        }
+    }
+
+    /// 番号での移動に使うキーか (割り当ての無い数字と，番号を打っている間の Enter / Esc / Backspace)
+    fn is_jump_key(&self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char(c) => c.is_ascii_digit() && self.keymap.action(code, Action::FOCUSED).is_none(),
+            KeyCode::Enter | KeyCode::Esc | KeyCode::Backspace => !self.jump.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// 番号を打って Enter でその番号の todo を選択 (別の列なら列も移る)
+    fn on_jump_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char(c) => self.jump.push(c),
+            KeyCode::Backspace => { self.jump.pop(); }
+            KeyCode::Esc => self.jump.clear(),
+            KeyCode::Enter => {
+                let target = std::mem::take(&mut self.jump).parse::<usize>().ok().and_then(|n| n.checked_sub(1)).map(Row::Task);
+                let found = [Focus::Due, Focus::NoDue].into_iter().find_map(|block| {
+                    let i = self.column_rows(block).iter().position(|row| Some(row) == target.as_ref())?;
+                    Some((block, i))
+                });
+                match found {
+                    Some((block, i)) => {
+                        self.focus = block;
+                        self.selected = i;
+                        self.select_by(0);
+                    }
+                    None => self.error = Some("No task with that number in the current view".into()),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 選択中の todo に `verb` (`toggle` / `up` など) を実行し，表示中の一覧を作り直す
+    /// (同じ todo が列に残っていれば選択したまま)
+    fn run_on_selected(&mut self, verb: &str) {
+        let Some(pos) = self.selected_pos() else { return };
+        let index = self.view[pos];
+        let last_view = self.session.last_view.clone();
+        let mut res = execute_command(&mut self.todos, &mut self.view, &mut self.session, &format!("{} {}", verb, pos + 1));
+        if res.error.is_none() && let Some(command) = last_view {
+            let message = res.message.take();
+            res = execute_command(&mut self.todos, &mut self.view, &mut self.session, &command);
+            res.message = res.message.or(message);
+        }
+        self.error = res.error;
+        self.message = res.message;
+        if let Some(selected) = self.focused_rows().iter().position(|row| matches!(row, &Row::Task(p) if self.view[p] == index)) {
+            self.selected = selected;
+            self.select_by(0);
+        }
+        self.clamp_scroll();
+    }
+
+    /// 入力中の `due:` の日付 (無ければ今日) でカレンダーを開く
+    fn open_picker(&mut self) {
+        let current = self.input.split_whitespace()
+            .filter_map(|w| w.strip_prefix("due:"))
+            .find_map(parse_due)
+            .map(|(d, _)| d);
+        self.picker = Some(current.unwrap_or_else(|| Local::now().date_naive()));
+    }
+
+    /// カレンダー操作: 矢印で日・週，PageUp / PageDown で月を移動し，Enter で `due:` を入力欄に入れる
+    fn on_picker_key(&mut self, code: KeyCode, date: NaiveDate) {
+        let moved = match code {
+            KeyCode::Left => date.checked_sub_days(Days::new(1)),
+            KeyCode::Right => date.checked_add_days(Days::new(1)),
+            KeyCode::Up => date.checked_sub_days(Days::new(7)),
+            KeyCode::Down => date.checked_add_days(Days::new(7)),
+            KeyCode::PageUp => date.checked_sub_months(Months::new(1)),
+            KeyCode::PageDown => date.checked_add_months(Months::new(1)),
+            KeyCode::Enter => {
+                self.insert_due(date);
+                None
+            }
+            _ => None,
+        };
+        self.picker = match code {
+            KeyCode::Enter | KeyCode::Esc => None,
+            _ => Some(moved.unwrap_or(date)),
+        };
+    }
+
+    /// 入力欄の `due:` を `date` に置き換える (無ければ末尾に足す)
+    fn insert_due(&mut self, date: NaiveDate) {
+        let token = format!("due:{}", date.format("%Y-%m-%d"));
+        let existing = self.input
+            .split(' ')
+            .scan(0, |start, w| {
+                let s = *start;
+                *start += w.len() + 1;
+                Some((s, w.len(), w.starts_with("due:")))
+            })
+            .find(|&(_, _, is_due)| is_due);
+        match existing {
+            Some((start, len, _)) => self.input.replace_range(start..start + len, &token),
+            None => {
+                if !self.input.is_empty() && !self.input.ends_with(' ') {
+                    self.input.push(' ');
+                }
+                self.input.push_str(&token);
+            }
+        }
+        self.cursor = self.grapheme_count();
     }
 
     fn apply_command(&mut self) {
-        let res = execute_command(&mut self.todos, &mut self.view, &self.input);
+        let input = match self.editing_index.take() {
+            Some(pos) => {
+                self.mode = InputMode::Focused;
+                format!("edit {} {}", pos + 1, self.input)
+            }
+            None => self.input.clone(),
+        };
+        let res = execute_command(&mut self.todos, &mut self.view, &mut self.session, &input);
+        if res.error.is_none() {
+            self.open_tag_menu(&Command::parse(&input));
+        }
         self.error = res.error;
+        self.message = res.message;
         self.input.clear();
         self.cursor = 0;
+        self.clamp_scroll();
+    }
+
+    /// `projects` / `contexts` を実行したらタグを選ぶメニューを開く
+    fn open_tag_menu(&mut self, cmd: &Command) {
+        let fold_case = self.session.config.fold_case;
+        let (sigil, counts) = match cmd {
+            Command::Projects => ('+', tag_counts(&self.todos, |t| &t.description.projects, fold_case)),
+            Command::Contexts => ('@', tag_counts(&self.todos, |t| &t.description.contexts, fold_case)),
+            _ => return,
+        };
+        if !counts.is_empty() {
+            let tags = counts.into_iter().map(|(tag, n)| (format!("{}{}", sigil, tag), n)).collect();
+            self.tag_menu = Some(TagMenu { tags, selected: 0 });
+        }
+    }
+
+    /// 終了キー: 未保存の変更があれば確認を始め，無ければ終了する (`true`)
+    fn request_quit(&mut self) -> bool {
+        self.confirm_quit = self.session.dirty;
+        !self.session.dirty
+    }
+
+    /// 終了の確認中のキー: y で終了 (`true`)，n / Esc で取り消し
+    fn on_confirm_quit_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('y') | KeyCode::Char('Y') => true,
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                self.confirm_quit = false;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    /// メニュー操作: 上下で選び，Enter で選んだタグの `filter` を実行，Esc で閉じる
+    fn on_tag_menu_key(&mut self, code: KeyCode) {
+        let Some(menu) = &mut self.tag_menu else { return };
+        match self.keymap.action(code, &[Action::FocusUp, Action::FocusDown, Action::Select, Action::Back]) {
+            Some(Action::FocusUp) => menu.selected = menu.selected.saturating_sub(1),
+            Some(Action::FocusDown) => menu.selected = (menu.selected + 1).min(menu.tags.len() - 1),
+            Some(Action::Select) => {
+                let command = menu.command();
+                self.tag_menu = None;
+                let res = execute_command(&mut self.todos, &mut self.view, &mut self.session, &command);
+                self.error = res.error;
+                self.message = res.message;
+                self.clamp_scroll();
+            }
+            Some(Action::Back) => self.tag_menu = None,
+            _ => {}
+        }
+    }
+
+    /// `n` 番目の書記素クラスタの開始バイト位置 (範囲外なら末尾)
+    fn grapheme_offset(&self, n: usize) -> usize {
+        self.input.grapheme_indices(true).nth(n).map(|(i, _)| i).unwrap_or(self.input.len())
+    }
+
+    fn grapheme_count(&self) -> usize {
+        self.input.graphemes(true).count()
+    }
+
+    /// `start..カーソル` を `word` で置き換え，カーソルを末尾へ
+    fn replace_token(&mut self, start: usize, word: &str) {
+        let end = self.grapheme_offset(self.cursor);
+        let start = start.min(end);
+        self.input.replace_range(start..end, word);
+        self.cursor = self.input[..start + word.len()].graphemes(true).count();
+    }
+
+    /// カーソル位置の `+`/`@` トークンを既存のプロジェクト・コンテキストで補完
+    fn complete(&mut self) {
+        if let Some(c) = &mut self.completion {
+            c.index = (c.index + 1) % c.candidates.len();
+            let (start, word) = (c.start, c.candidates[c.index].clone());
+            self.replace_token(start, &word);
+            return;
+        }
+        let end = self.grapheme_offset(self.cursor);
+        let start = self.input[..end].char_indices().rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let token = &self.input[start..end];
+        let tags: Vec<&String> = match token.chars().next() {
+            Some('+') => self.todos.iter().flat_map(|t| &t.description.projects).collect(),
+            Some('@') => self.todos.iter().flat_map(|t| &t.description.contexts).collect(),
+            _ => return,
+        };
+        let (sigil, prefix) = token.split_at(1);
+        let mut candidates: Vec<String> = tags.into_iter()
+            .filter(|t| t.starts_with(prefix))
+            .map(|t| format!("{}{}", sigil, quote(t)))
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        let Some(first) = candidates.first() else { return };
+
+        // 共通接頭辞まで伸ばせなければ候補の巡回を始める
+        let common = candidates.iter().fold(first.as_str(), |acc, c| {
+            let n = acc.char_indices().zip(c.chars())
+                .find(|((_, a), b)| a != b)
+                .map(|((i, _), _)| i)
+                .unwrap_or(acc.len().min(c.len()));
+            &acc[..n]
+        }).to_string();
+        if candidates.len() == 1 || common.len() > token.len() {
+            self.replace_token(start, &common);
+        } else {
+            let word = first.clone();
+            self.completion = Some(Completion { start, candidates, index: 0 });
+            self.replace_token(start, &word);
+        }
     }
 
     fn cursor_x(&self) -> u16 {
-        let end = self.input.char_indices().nth(self.cursor).map(|(i, _)| i).unwrap_or(self.input.len());
-        UnicodeWidthStr::width(&self.input[..end]) as u16
+        let end = self.grapheme_offset(self.cursor);
+        UnicodeWidthStr::width(&self.input[..end]).min(u16::MAX as usize) as u16
+    }
+
+    /// 編集モードのキー処理
+    fn on_editing_key(&mut self, code: KeyCode) {
+        if code != KeyCode::Tab {
+            self.completion = None;
+        }
+        // どの経路でカーソルがずれても文字列の範囲外を指さないようにする
+        self.cursor = self.cursor.min(self.grapheme_count());
+        match code {
+            KeyCode::Enter => self.apply_command(),
+            KeyCode::Tab => self.complete(),
+            KeyCode::Char(c) => {
+                // 結合文字は直前のクラスタに合流するので挿入後に数え直す
+                let idx = self.grapheme_offset(self.cursor);
+                self.input.insert(idx, c);
+                self.cursor = self.input[..idx + c.len_utf8()].graphemes(true).count();
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                let start = self.grapheme_offset(self.cursor - 1);
+                let end = self.grapheme_offset(self.cursor);
+                self.input.replace_range(start..end, "");
+                self.cursor -= 1;
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.grapheme_count()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.grapheme_count(),
+            KeyCode::Esc if self.editing_index.take().is_some() => {
+                self.input.clear();
+                self.cursor = 0;
+                self.mode = InputMode::Focused;
+            }
+            KeyCode::Esc => self.mode = InputMode::Normal,
+            _ => {}
+        }
+    }
+
+    /// 列内で画面上の行 `row` にある項目 (列内の位置)
+    fn item_at(&self, block: Focus, row: u16) -> Option<usize> {
+        let today = Local::now().date_naive();
+        let area = self.area(block);
+        let mut y = area.y + 1;
+        for (i, item) in self.column_rows(block).iter().enumerate().skip(self.scroll(block)).take(self.rows(block)) {
+            y += self.row_item(item, area.width, today).height() as u16;
+            if row < y {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// マウス操作: ホイールでポインタ下の列をスクロール，左クリックでフォーカスと選択 (ポップアップ表示中は無視)
+    fn on_mouse(&mut self, m: MouseEvent) {
+        // ポップアップや終了の確認を開いている間はキー操作だけを受け付ける
+        if self.tag_menu.is_some() || self.picker.is_some() || self.confirm_quit || matches!(self.mode, InputMode::Detail) {
+            return;
+        }
+        let at = Position::new(m.column, m.row);
+        let Some(block) = [Focus::Input, Focus::Due, Focus::NoDue].into_iter().find(|&b| self.area(b).contains(at)) else {
+            return;
+        };
+        match m.kind {
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let delta = if m.kind == MouseEventKind::ScrollUp { -1 } else { 1 };
+                let (len, rows) = (self.column_rows(block).len(), self.rows(block));
+                if let Some(scroll) = self.scroll_mut(block) {
+                    *scroll = clamp_offset(scroll.saturating_add_signed(delta), len, rows);
+                }
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                if self.editing_index.take().is_some() {
+                    self.input.clear();
+                    self.cursor = 0;
+                }
+                self.focus = block;
+                if block == Focus::Input {
+                    self.mode = InputMode::Editing;
+                } else {
+                    self.selected = self.item_at(block, m.row).unwrap_or(self.scroll(block));
+                    self.mode = InputMode::Focused;
+                    self.select_by(0);
+                }
+            }
+            _ => {}
+        }
     }
 
     pub fn run(mut self, mut term: DefaultTerminal) -> Result<()> {
         loop {
             term.draw(|f| self.draw(f))?;
-            if let Event::Key(key) = event::read()? {
-                match self.mode {
-                    InputMode::Normal => match key.code {
-                        KeyCode::Char('e') => self.mode = InputMode::Editing,
-                        KeyCode::Char('q') => break,
-                        _ => {}
-                    },
-                    InputMode::Editing if key.kind == KeyEventKind::Press => match key.code {
-                        KeyCode::Enter => self.apply_command(),
-                        KeyCode::Char(c) => {
-                            let idx = self.input.char_indices().map(|(i, _)| i)
-                                .nth(self.cursor).unwrap_or(self.input.len());
-                            self.input.insert(idx, c);
-                            self.cursor += 1;
-                        }
-                        KeyCode::Backspace => {
-                            if self.cursor > 0 {
-                                let mut cs: Vec<char> = self.input.chars().collect();
-                                cs.remove(self.cursor - 1);
-                                self.input = cs.into_iter().collect();
-                                self.cursor -= 1;
-                            }
-                        }
-                        KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
-                        KeyCode::Right => self.cursor = (self.cursor + 1).min(self.input.chars().count()),
-                        KeyCode::Esc => self.mode = InputMode::Normal,
-                        _ => {}
-                    },
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                Event::Mouse(m) => {
+                    self.on_mouse(m);
+                    continue;
+                }
+                _ => continue,
+            };
+            if self.confirm_quit {
+                if key.kind == KeyEventKind::Press && self.on_confirm_quit_key(key.code) {
+                    break;
+                }
+                continue;
+            }
+            if self.tag_menu.is_some() {
+                if key.kind == KeyEventKind::Press {
+                    self.on_tag_menu_key(key.code);
+                }
+                continue;
+            }
+            match self.mode {
+                InputMode::Normal => match self.keymap.action(key.code, Action::NORMAL) {
+                    Some(Action::Edit) => {
+                        self.focus = Focus::Input;
+                        self.mode = InputMode::Editing;
+                    }
+                    Some(Action::Add) => self.start_typing("add "),
+                    Some(Action::Search) => self.start_typing("search "),
+                    Some(Action::Quit) if self.request_quit() => break,
+                    Some(Action::FocusUp) => self.move_focus(-1, false),
+                    Some(Action::FocusDown) => self.move_focus(1, false),
+                    Some(Action::FocusNext) => self.move_focus(1, true),
+                    Some(Action::FocusPrev) => self.move_focus(-1, true),
+                    Some(Action::Select) if self.focus == Focus::Input => self.mode = InputMode::Editing,
+                    Some(Action::Select) => {
+                        self.selected = match self.focus {
+                            Focus::NoDue => self.nodue_scroll,
+                            _ => self.due_scroll,
+                        };
+                        self.mode = InputMode::Focused;
+                    }
+                    _ => {}
+                },
+                InputMode::Focused if key.kind == KeyEventKind::Press && self.is_jump_key(key.code) => self.on_jump_key(key.code),
+                InputMode::Focused if key.kind == KeyEventKind::Press => match self.keymap.action(key.code, Action::FOCUSED) {
+                    Some(Action::FocusUp) => self.select_by(-1),
+                    Some(Action::FocusDown) => self.select_by(1),
+                    Some(Action::PageUp) => self.select_by(-(self.rows(self.focus) as isize)),
+                    Some(Action::PageDown) => self.select_by(self.rows(self.focus) as isize),
+                    Some(Action::Top) => self.select_by(isize::MIN),
+                    Some(Action::Bottom) => self.select_by(isize::MAX),
+                    Some(Action::Edit) => self.start_edit(),
+                    Some(Action::Copy) => self.copy_selected(),
+                    Some(Action::Toggle) => self.run_on_selected("toggle"),
+                    Some(Action::RaisePriority) => self.run_on_selected("up"),
+                    Some(Action::LowerPriority) => self.run_on_selected("down"),
+                    Some(Action::Select) => self.open_selected(),
+                    Some(Action::Back) => self.mode = InputMode::Normal,
                     _ => {}
+                },
+                InputMode::Detail if key.kind == KeyEventKind::Press && self.keymap.action(key.code, &[Action::Back]).is_some() => {
+                    self.mode = InputMode::Focused;
                 }
+                InputMode::Editing if key.kind == KeyEventKind::Press => match self.picker {
+                    Some(date) => self.on_picker_key(key.code, date),
+                    None if key.code == KeyCode::Char('d') && key.modifiers.contains(KeyModifiers::CONTROL) => self.open_picker(),
+                    None => self.on_editing_key(key.code),
+                },
+                _ => {}
             }
         }
         Ok(())
     }
 
-    fn draw(&self, f: &mut Frame) {
+    fn draw(&mut self, f: &mut Frame) {
         let today = Local::now().date_naive();
         let chunks = Layout::vertical([
             Constraint::Length(1),
@@ -90,76 +823,815 @@ impl App {
         ]).split(f.area());
 
         // ヘッダー
-        let header = if let Some(err) = &self.error {
-            Paragraph::new(err.clone()).style(Style::default().fg(Color::Red))
+        let header = if self.confirm_quit {
+            Paragraph::new(UNSAVED_PROMPT).style(self.theme().error)
+        } else if let Some(err) = &self.error {
+            Paragraph::new(err.clone()).style(self.theme().error)
+        } else if let Some(msg) = &self.message {
+            Paragraph::new(msg.clone()).style(self.theme().message)
         } else {
+            let key = |a: Action| self.keymap.label(a).bold();
+            let updown = format!("{}{}", self.keymap.label(Action::FocusUp), self.keymap.label(Action::FocusDown)).bold();
             let (msg, style) = match self.mode {
                 InputMode::Normal => (
-                    vec!["Press ".into(), "q".bold(), " to quit, ".into(), "e".bold(), " to edit.".into()],
+                    vec![
+                        "Press ".into(), key(Action::Quit), " to quit, ".into(), key(Action::Edit), " to edit, ".into(),
+                        key(Action::Add), " to add, ".into(), key(Action::Search), " to search, ".into(),
+                        updown, " to move, ".into(), key(Action::Select), " to select.".into(),
+                    ],
                     Style::default().add_modifier(Modifier::RAPID_BLINK),
                 ),
+                InputMode::Focused if !self.jump.is_empty() => (
+                    vec![
+                        "Jump to ".into(), self.jump.clone().bold(), " — press ".into(), "Enter".bold(), " to go, ".into(),
+                        "Esc".bold(), " to cancel.".into(),
+                    ],
+                    Style::default(),
+                ),
+                InputMode::Focused => (
+                    vec![
+                        "Press ".into(), key(Action::Back), " to leave, ".into(), updown, " to select, ".into(),
+                        format!("{}/{}", self.keymap.label(Action::Top), self.keymap.label(Action::Bottom)).bold(),
+                        " to jump to top/bottom, ".into(), key(Action::Edit), " to edit, ".into(),
+                        key(Action::Copy), " to copy, ".into(), key(Action::Toggle), " to toggle done, ".into(),
+                        format!("{}/{}", self.keymap.label(Action::RaisePriority), self.keymap.label(Action::LowerPriority)).bold(),
+                        " to change priority, ".into(), key(Action::Select), " for details.".into(),
+                    ],
+                    Style::default(),
+                ),
+                InputMode::Detail => (vec!["Press ".into(), key(Action::Back), " to close.".into()], Style::default()),
+                InputMode::Editing if self.picker.is_some() => (
+                    vec![
+                        "Press ".into(), "←→↑↓".bold(), " to move, ".into(), "PgUp/PgDn".bold(), " to change month, ".into(),
+                        "Enter".bold(), " to insert, ".into(), "Esc".bold(), " to close.".into(),
+                    ],
+                    Style::default(),
+                ),
+                InputMode::Editing if self.editing_index.is_some() => (
+                    vec![
+                        "Press ".into(), "Esc".bold(), " to cancel, ".into(), "Enter".bold(), " to save the task, ".into(),
+                        "Ctrl+D".bold(), " to pick a due date.".into(),
+                    ],
+                    Style::default(),
+                ),
                 InputMode::Editing => (
-                    vec!["Press ".into(), "Esc".bold(), " to cancel, ".into(), "Enter".bold(), " to run.".into()],
+                    vec![
+                        "Press ".into(), "Esc".bold(), " to cancel, ".into(), "Enter".bold(), " to run, ".into(),
+                        "Ctrl+D".bold(), " to pick a due date.".into(),
+                    ],
                     Style::default(),
                 ),
             };
             Paragraph::new(Text::from(Line::from(msg)).patch_style(style))
         };
-        f.render_widget(header, chunks[0]);
+        // 未保存の変更があれば先頭に `*`
+        let header_area = if self.session.dirty {
+            let [mark, rest] = Layout::horizontal([Constraint::Length(2), Constraint::Min(1)]).areas(chunks[0]);
+            f.render_widget(Paragraph::new("*").bold(), mark);
+            rest
+        } else {
+            chunks[0]
+        };
+        f.render_widget(header, header_area);
 
         // 入力欄
+        // 長い入力はカーソルが見える位置まで横にずらす
+        let inner_width = chunks[1].width.saturating_sub(2);
+        let offset = self.cursor_x().saturating_sub(inner_width.saturating_sub(1));
         let input = Paragraph::new(self.input.as_str())
-            .style(if matches!(self.mode, InputMode::Editing) { Style::default().fg(Color::Yellow) } else { Style::default() })
-            .block(Block::bordered().title("Input"));
+            .style(if matches!(self.mode, InputMode::Editing) { self.theme().editing } else { Style::default() })
+            .scroll((0, offset))
+            .block(Block::bordered().title(format!("Input ({})", self.session.file.display())).border_style(self.border_style(Focus::Input)));
         f.render_widget(input, chunks[1]);
         if matches!(self.mode, InputMode::Editing) {
-            f.set_cursor_position((chunks[1].x + self.cursor_x() + 1, chunks[1].y + 1));
+            f.set_cursor_position((chunks[1].x + 1 + (self.cursor_x() - offset), chunks[1].y + 1));
         }
 
-        // TODO リスト表示
+        // TODO リスト表示 (`by-project` では全体を 1 列で使う)
         let cols = Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).split(chunks[2]);
-        let sep = |w: u16| Line::from(vec![Span::raw(" "), Span::raw("-".repeat((w - 2) as usize))]);
-
-        let mut due_items = Vec::new();
-        let mut nodue_items = Vec::new();
-
-        for (idx, &i) in self.view.iter().enumerate() {
-            let t = &self.todos[i];
-            // マーカー色
-            let marker_color = t.marker_color(today);
-            let marker = Span::styled(" ", Style::default().bg(marker_color));
-
-            let mut lines = Vec::new();
-            lines.push(sep(cols[0].width));
-            // 見出し行
-            let head = format!("{}: {}{}{}",
-                idx + 1,
-                if t.completion { "x " } else { "" },
-                t.priority.map(|p| format!("({}) ", p)).unwrap_or_default(),
-                t.description.content
-            );
-            lines.push(Line::from(vec![marker.clone(), Span::raw(head)]));
-            // 日付行
-            if t.completion_date.is_some() || t.creation_date.is_some() {
-                let cd = t.completion_date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default();
-                let cr = t.creation_date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default();
-                lines.push(Line::from(vec![marker.clone(), Span::raw(format!("     {} {}", cd, cr))]));
-            }
-            // タグ行 & due
-            if let Some(p) = &t.description.project { lines.push(Line::from(vec![marker.clone(), Span::raw(format!("      +{}", p))])); }
-            if let Some(c) = &t.description.context { lines.push(Line::from(vec![marker.clone(), Span::raw(format!("      @{}", c))])); }
-            if let Some(d) = t.description.due { lines.push(Line::from(vec![marker.clone(), Span::raw(format!("      due:{}", d.format("%Y-%m-%d"))) ])); }
-            lines.push(sep(cols[0].width));
-
-            let item = ListItem::new(Text::from(lines));
-            if t.description.due.is_some() {
-                due_items.push(item);
+        let (due_area, nodue_area) = if self.session.config.by_project {
+            (chunks[2], Rect::default())
+        } else if self.session.config.nodue_left {
+            (cols[1], cols[0])
+        } else {
+            (cols[0], cols[1])
+        };
+        (self.input_area, self.due_area, self.nodue_area) = (chunks[1], due_area, nodue_area);
+        let (due, nodue) = (self.column_rows(Focus::Due), self.column_rows(Focus::NoDue));
+        self.due_rows = rows_for_height(due_area.height, self.lines_per_task(&due, due_area.width, today));
+        self.nodue_rows = rows_for_height(nodue_area.height, self.lines_per_task(&nodue, nodue_area.width, today));
+        // 端末のリサイズで表示件数が変わってもスクロール位置を範囲内に保つ
+        self.clamp_scroll();
+        self.render_column(f, due_area, Focus::Due, &due, today);
+        if !self.session.config.by_project {
+            self.render_column(f, nodue_area, Focus::NoDue, &nodue, today);
+        }
+
+        // 期日選択のカレンダー
+        if let Some(date) = self.picker {
+            self.render_picker(f, due_area, date, today);
+        }
+
+        // タグを選ぶメニュー
+        if let Some(menu) = &self.tag_menu {
+            self.render_tag_menu(f, chunks[2], menu);
+        }
+
+        // 選択中の todo の詳細
+        if matches!(self.mode, InputMode::Detail) && let Some(pos) = self.selected_pos() {
+            self.render_detail(f, chunks[1].union(chunks[2]), pos, today);
+        }
+    }
+
+    /// タグのメニューを `area` の中央に重ねて描く
+    fn render_tag_menu(&self, f: &mut Frame, area: Rect, menu: &TagMenu) {
+        let lines: Vec<Line> = menu.tags
+            .iter()
+            .enumerate()
+            .map(|(i, (tag, n))| {
+                let line = Line::from(format!(" {} ({})", tag, n));
+                if i == menu.selected { line.reversed() } else { line }
+            })
+            .collect();
+        let title = format!(" {}: filter / {}: close ", self.keymap.label(Action::Select), self.keymap.label(Action::Back));
+        let width = lines.iter().map(|l| l.width()).max().unwrap_or(0).max(title.width()) as u16 + 3;
+        let height = lines.len() as u16 + 2;
+        let popup = Rect {
+            x: area.x + area.width.saturating_sub(width) / 2,
+            y: area.y + area.height.saturating_sub(height) / 2,
+            width,
+            height,
+        }.intersection(area);
+        let scroll = (menu.selected as u16).saturating_sub(popup.height.saturating_sub(3));
+        let list = Paragraph::new(lines)
+            .scroll((scroll, 0))
+            .block(Block::bordered().title(title).border_style(self.theme().focus));
+        f.render_widget(Clear, popup);
+        f.render_widget(list, popup);
+    }
+
+    /// `view` 上の位置 `pos` の todo の全項目を `area` いっぱいに描く
+    fn render_detail(&self, f: &mut Frame, area: Rect, pos: usize, today: NaiveDate) {
+        let fields = self.todos[self.view[pos]].details(today, &self.session.config.date_format);
+        let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let lines: Vec<Line> = fields
+            .into_iter()
+            .map(|(name, value)| Line::from(vec![format!("{:>width$}: ", name).bold(), Span::raw(value)]))
+            .collect();
+        let detail = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(format!("Task {}", pos + 1)).border_style(self.theme().focus));
+        f.render_widget(Clear, area);
+        f.render_widget(detail, area);
+    }
+
+    fn theme(&self) -> &Theme {
+        &self.session.config.theme
+    }
+
+    /// `date` を含む月のカレンダーを `area` の左上に重ねて描く
+    fn render_picker(&self, f: &mut Frame, area: Rect, date: NaiveDate, today: NaiveDate) {
+        let popup = Rect { x: area.x + 1, y: area.y, width: 22, height: 9 }.intersection(area);
+        let first = date.with_day(1).unwrap_or(date);
+        let mut lines = vec![Line::from("Mo Tu We Th Fr Sa Su").bold()];
+        let mut week = vec![Span::raw("   ".repeat(first.weekday().num_days_from_monday() as usize))];
+        for d in first.iter_days().take_while(|d| d.month() == first.month()) {
+            let mut style = Style::default();
+            if d == today { style = style.add_modifier(Modifier::UNDERLINED); }
+            if d == date { style = style.add_modifier(Modifier::REVERSED); }
+            week.push(Span::styled(format!("{:>2}", d.day()), style));
+            if d.weekday().num_days_from_monday() == 6 {
+                lines.push(Line::from(std::mem::take(&mut week)));
             } else {
-                nodue_items.push(item);
+                week.push(Span::raw(" "));
             }
         }
+        if !week.is_empty() {
+            lines.push(Line::from(week));
+        }
+        let calendar = Paragraph::new(lines).block(
+            Block::bordered().title(date.format(" %Y-%m ").to_string()).border_style(self.theme().focus),
+        );
+        f.render_widget(Clear, popup);
+        f.render_widget(calendar, popup);
+    }
+
+    fn border_style(&self, block: Focus) -> Style {
+        if self.focus == block { self.theme().focus } else { Style::default() }
+    }
+
+    /// 1 列分のリストとスクロールバーを描画
+    fn render_column(&self, f: &mut Frame, area: Rect, block: Focus, positions: &[Row], today: NaiveDate) {
+        let (title, scroll) = match block {
+            _ if self.session.config.by_project => ("Todos by Project", self.due_scroll),
+            Focus::NoDue => ("No-Due Todos", self.nodue_scroll),
+            _ => ("Due Todos", self.due_scroll),
+        };
+        let rows = self.rows(block);
+        let end = (scroll + rows).min(positions.len());
+        let selected = (matches!(self.mode, InputMode::Focused) && self.focus == block).then_some(self.selected);
+        let items: Vec<ListItem> = positions[scroll.min(end)..end]
+            .iter()
+            .enumerate()
+            .map(|(i, row)| {
+                let item = self.row_item(row, area.width, today);
+                if selected == Some(scroll + i) { item.add_modifier(Modifier::REVERSED) } else { item }
+            })
+            .collect();
+        // 全件数は絞り込む前の一覧 (隠した todo を除く) で数える
+        let base = default_view(&self.todos, &self.session);
+        let has_due = |&&i: &&usize| self.todos[i].description.due.is_some();
+        let (shown, total) = match block {
+            _ if self.session.config.by_project => (self.view.len(), base.len()),
+            Focus::NoDue => (positions.len(), base.iter().filter(|i| !has_due(i)).count()),
+            _ => (positions.len(), base.iter().filter(has_due).count()),
+        };
+        let title = column_title(title, shown, total, self.view != base);
+        f.render_widget(List::new(items).block(Block::bordered().title(title).border_style(self.border_style(block))), area);
+
+        if positions.is_empty() {
+            let inner = area.inner(Margin { vertical: 1, horizontal: 1 });
+            let line = Rect { y: inner.y + inner.height / 2, height: inner.height.min(2), ..inner };
+            let hint = Paragraph::new(self.empty_message(block))
+                .centered()
+                .wrap(Wrap { trim: true })
+                .style(self.theme().hint);
+            f.render_widget(hint, line);
+        }
+
+        if self.focus == block || positions.len() > rows {
+            let mut state = ScrollbarState::new(positions.len().saturating_sub(rows) + 1).position(scroll);
+            f.render_stateful_widget(
+                Scrollbar::new(ScrollbarOrientation::VerticalRight),
+                area.inner(Margin { vertical: 1, horizontal: 0 }),
+                &mut state,
+            );
+        }
+    }
+
+    /// 列内で最も行数の多い項目の行数
+    fn lines_per_task(&self, positions: &[Row], width: u16, today: NaiveDate) -> u16 {
+        positions.iter().map(|row| self.row_item(row, width, today).height() as u16).max().unwrap_or(1)
+    }
+
+    /// 空の列に表示する案内文
+    fn empty_message(&self, block: Focus) -> &'static str {
+        let has_due = |t: &Todo| t.description.due.is_some();
+        let exists = match block {
+            _ if self.session.config.by_project => !self.todos.is_empty(),
+            Focus::NoDue => self.todos.iter().any(|t| !has_due(t)),
+            _ => self.todos.iter().any(has_due),
+        };
+        match block {
+            _ if self.todos.is_empty() => "No tasks yet — type 'add ...' to create one",
+            _ if exists => "Nothing here in the current view — type 'list' to show all",
+            Focus::NoDue => "No tasks without a due date — type 'add ...'",
+            _ => "No due tasks — type 'add ... due:YYYY-MM-DD'",
+        }
+    }
 
-        f.render_widget(List::new(due_items).block(Block::bordered().title("Due Todos")), cols[0]);
-        f.render_widget(List::new(nodue_items).block(Block::bordered().title("No-Due Todos")), cols[1]);
+    /// 本文を `search` の文字列に一致する部分だけ反転して表示する
+    fn content_spans(&self, content: &str) -> Vec<Span<'static>> {
+        match &self.session.search {
+            Some(query) => split_matches(content, query, self.session.config.fold_case)
+                .into_iter()
+                .map(|(s, hit)| if hit { Span::raw(s.to_string()).reversed() } else { Span::raw(s.to_string()) })
+                .collect(),
+            None => vec![Span::raw(content.to_string())],
+        }
     }
-}
\ No newline at end of file
+
+    /// 列の 1 行分を整形 (見出しは `▼ +project (件数)`，畳んでいれば `▶`)
+    fn row_item(&self, row: &Row, width: u16, today: NaiveDate) -> ListItem<'static> {
+        match row {
+            Row::Header(project, n) => {
+                let mark = if self.collapsed.contains(project) { '▶' } else { '▼' };
+                let name = project.as_ref().map_or("Unsorted".to_string(), |p| format!("+{}", p));
+                ListItem::new(Line::from(format!(" {} {} ({})", mark, name, n)).bold())
+            }
+            &Row::Task(pos) => self.task_item(pos, width, today),
+        }
+    }
+
+    /// `view` 上の位置 `pos` の todo を複数行の項目に整形
+    fn task_item(&self, pos: usize, width: u16, today: NaiveDate) -> ListItem<'static> {
+        let t = &self.todos[self.view[pos]];
+        let date = |d: NaiveDate| d.format(&self.session.config.date_format).to_string();
+        // 期日は設定で曜日を添える
+        let due_date = |d: NaiveDate| {
+            if self.session.config.show_weekday { format!("{} ({})", date(d), d.format("%a")) } else { date(d) }
+        };
+        let sep = || Line::from(vec![Span::raw(" "), Span::raw("-".repeat(width.saturating_sub(2) as usize))]);
+        // マーカー
+        let urgency = t.urgency(today);
+        let marker = self.theme().marker(urgency);
+
+        let mut lines = Vec::new();
+        // `when` の区分の切れ目に見出し
+        if self.session.grouped
+            && !self.session.config.by_project
+            && let Some(bucket) = t.bucket(today)
+            && (pos == 0 || self.todos[self.view[pos - 1]].bucket(today) != Some(bucket))
+        {
+            lines.push(Line::from(format!(" ── {} ──", bucket.label())).bold());
+        }
+        // 1 行表示
+        if self.session.config.compact {
+            let mut spans = vec![marker, Span::raw(format!(" {} ", pos + 1))];
+            if urgency == Urgency::Overdue {
+                spans.push(Span::raw("(!) "));
+            }
+            if t.completion {
+                spans.push(Span::raw("x "));
+            }
+            if let Some(p) = t.priority {
+                spans.push(Span::raw(format!("({}) ", p)));
+            }
+            spans.extend(self.content_spans(&t.description.content));
+            for p in &t.description.projects {
+                spans.push(Span::styled(format!(" +{}", p), self.theme().tag(p, false)));
+            }
+            for c in &t.description.contexts {
+                spans.push(Span::styled(format!(" @{}", c), self.theme().tag(c, true)));
+            }
+            if let Some(d) = t.description.due {
+                let time = t.description.due_time.map(|t| t.format(" %H:%M").to_string()).unwrap_or_default();
+                spans.push(Span::raw(format!(" due:{}{}", due_date(d), time)));
+            }
+            lines.push(Line::from(spans));
+            return ListItem::new(Text::from(lines));
+        }
+        let separator = self.session.config.separator;
+        if separator == Separator::Line {
+            lines.push(sep());
+        }
+        // 見出し行
+        let head = format!("{}: {}{}{}",
+            pos + 1,
+            if urgency == Urgency::Overdue { "(!) " } else { "" },
+            if t.completion { "x " } else { "" },
+            t.priority.map(|p| format!("({}) ", p)).unwrap_or_default(),
+        );
+        let mut spans = vec![marker.clone(), Span::raw(head)];
+        spans.extend(self.content_spans(&t.description.content));
+        lines.push(Line::from(spans));
+        // 日付行
+        if t.completion_date.is_some() || t.creation_date.is_some() {
+            let cd = t.completion_date.map(date).unwrap_or_default();
+            let cr = t.creation_date.map(date).unwrap_or_default();
+            let age = t.age(today).filter(|_| !t.completion).map(|a| format!(" (age {}d)", a)).unwrap_or_default();
+            lines.push(Line::from(vec![marker.clone(), Span::raw(format!("     {} {}{}", cd, cr, age))]));
+        }
+        // タグ行 & due
+        for p in &t.description.projects {
+            lines.push(Line::from(vec![marker.clone(), Span::raw("      "), Span::styled(format!("+{}", p), self.theme().tag(p, false))]));
+        }
+        for c in &t.description.contexts {
+            lines.push(Line::from(vec![marker.clone(), Span::raw("      "), Span::styled(format!("@{}", c), self.theme().tag(c, true))]));
+        }
+        if let Some(d) = t.description.due {
+            let rel = t.due_relative(today).map(|r| format!(" ({})", r)).unwrap_or_default();
+            let time = t.description.due_time.map(|t| t.format(" %H:%M").to_string()).unwrap_or_default();
+            lines.push(Line::from(vec![marker.clone(), Span::raw(format!("      due:{}{}{}", due_date(d), time, rel))]));
+        }
+        // メモ
+        if let Some(note) = t.note() {
+            lines.push(Line::from(vec![marker.clone(), Span::raw("        "), Span::raw(note.to_string()).italic()]));
+        }
+        // 注釈
+        for a in &t.annotations {
+            lines.push(Line::from(vec![marker.clone(), Span::raw("        "), Span::raw(a.trim().to_string()).dim()]));
+        }
+        match separator {
+            Separator::Line => lines.push(sep()),
+            Separator::Blank => lines.push(Line::default()),
+            Separator::None => {}
+        }
+
+        ListItem::new(Text::from(lines))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+    fn app(lines: &[&str]) -> App {
+        let todos = lines.iter().map(|l| Todo::parse(l)).collect();
+        App::with_session(todos, Session::new(Config::default()), None)
+    }
+
+    /// `lines` を書いた一時ディレクトリの todo ファイルを開いた状態 (`name` はテストごとに変える)
+    fn app_on_file(name: &str, lines: &[&str]) -> App {
+        let dir = std::env::temp_dir().join(format!("todo-app-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut session = Session::new(Config::default());
+        session.file = dir.join("todo.txt");
+        std::fs::write(&session.file, lines.iter().map(|l| format!("{}\n", l)).collect::<String>()).unwrap();
+        App::with_session(load_all(&session.file), session, None)
+    }
+
+    /// `width` x `height` の画面に描いた結果
+    fn draw(app: &mut App, width: u16, height: u16) -> Buffer {
+        let mut term = Terminal::new(TestBackend::new(width, height)).unwrap();
+        term.draw(|f| app.draw(f)).unwrap();
+        term.backend().buffer().clone()
+    }
+
+    /// 描いた画面の文字を行ごとにつなげる
+    fn screen(buffer: &Buffer) -> String {
+        let area = buffer.area;
+        (area.top()..area.bottom())
+            .map(|y| (area.left()..area.right()).map(|x| buffer[(x, y)].symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn size_warning_triggers_only_over_the_limit() {
+        assert!(size_warning(1001, 1000).is_some_and(|w| w.contains("1001 lines")));
+        assert_eq!(size_warning(1000, 1000), None);
+        assert_eq!(size_warning(10, 1000), None);
+        assert_eq!(size_warning(5000, 0), None);
+    }
+
+    #[test]
+    fn very_long_input_draws_without_overflow() {
+        let mut a = app(&[]);
+        a.mode = InputMode::Editing;
+        a.input = "漢".repeat(40_000);
+        a.cursor = a.grapheme_count();
+        draw(&mut a, 80, 20);
+    }
+
+    /// 編集モードで `text` を 1 文字ずつ打つ
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            app.on_editing_key(KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn multi_codepoint_grapheme_is_one_step() {
+        let mut a = app(&[]);
+        // 旗の絵文字 (2 コードポイント) と結合文字付きの e
+        type_text(&mut a, "a\u{1F1EF}\u{1F1F5}e\u{301}");
+        assert_eq!(a.cursor, 3);
+        assert_eq!(a.cursor_x(), 4);
+        a.on_editing_key(KeyCode::Left);
+        a.on_editing_key(KeyCode::Backspace);
+        assert_eq!(a.input, "ae\u{301}");
+        assert_eq!(a.cursor, 1);
+        a.on_editing_key(KeyCode::Right);
+        a.on_editing_key(KeyCode::Backspace);
+        assert_eq!(a.input, "a");
+        assert_eq!(a.cursor, 1);
+    }
+
+    #[test]
+    fn rendering_uses_date_format() {
+        let mut a = app(&["2024-06-01 report due:2024-06-15"]);
+        a.session.config.date_format = "%d/%m/%Y".into();
+        let text = screen(&draw(&mut a, 100, 20));
+        assert!(text.contains("15/06/2024"), "{}", text);
+        assert!(text.contains("01/06/2024"), "{}", text);
+        assert!(!text.contains("2024-06-15"), "{}", text);
+        assert_eq!(a.todos[0].format(), "2024-06-01 report due:2024-06-15");
+    }
+
+    #[test]
+    fn rows_for_height_fits_whole_tasks() {
+        assert_eq!(rows_for_height(22, 1), 20);
+        assert_eq!(rows_for_height(22, 4), 5);
+        assert_eq!(rows_for_height(22, 0), 20);
+        // 枠だけでも 1 件は表示する
+        assert_eq!(rows_for_height(2, 3), 1);
+        assert_eq!(rows_for_height(0, 3), 1);
+    }
+
+    #[test]
+    fn clamp_offset_keeps_the_last_page_full() {
+        assert_eq!(clamp_offset(0, 30, 10), 0);
+        assert_eq!(clamp_offset(15, 30, 10), 15);
+        assert_eq!(clamp_offset(25, 30, 10), 20);
+        assert_eq!(clamp_offset(5, 3, 10), 0);
+        assert_eq!(clamp_offset(5, 0, 10), 0);
+    }
+
+    #[test]
+    fn startup_message_hints_at_a_missing_file() {
+        let dir = std::env::temp_dir().join(format!("todo-app-{}-missing", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo.txt");
+        assert!(startup_message(&path, 1000).is_some_and(|m| m.starts_with("No todo.txt found")));
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(startup_message(&path, 1000), None);
+        std::fs::write(&path, "a\nb\n").unwrap();
+        assert!(startup_message(&path, 1).is_some_and(|m| m.contains("2 lines")));
+    }
+
+    #[test]
+    fn jumping_to_the_bottom_scrolls_a_full_page() {
+        let lines: Vec<String> = (1..=30).map(|n| format!("task {}", n)).collect();
+        let mut app = app(&lines.iter().map(String::as_str).collect::<Vec<_>>());
+        draw(&mut app, 80, 20);
+        (app.focus, app.mode) = (Focus::NoDue, InputMode::Focused);
+        let rows = app.nodue_rows;
+        assert!(rows > 0 && rows < 30);
+        app.select_by(isize::MAX);
+        assert_eq!((app.selected, app.nodue_scroll), (29, 30 - rows));
+        assert!(screen(&draw(&mut app, 80, 20)).contains("task 30"));
+        app.select_by(isize::MIN);
+        assert_eq!((app.selected, app.nodue_scroll), (0, 0));
+    }
+
+    #[test]
+    fn column_titles_show_filtered_counts() {
+        assert_eq!(column_title("Due Todos", 3, 3, false), "Due Todos (3)");
+        assert_eq!(column_title("Due Todos", 1, 3, true), "Due Todos (1/3)");
+        let mut app = app(&["a due:2030-01-01", "b +x due:2030-01-02", "c +x", "d"]);
+        let text = screen(&draw(&mut app, 100, 20));
+        assert!(text.contains("Due Todos (2)") && text.contains("No-Due Todos (2)"), "{}", text);
+        app.input = "filter +x".into();
+        app.apply_command();
+        let text = screen(&draw(&mut app, 100, 20));
+        assert!(text.contains("Due Todos (1/2)") && text.contains("No-Due Todos (1/2)"), "{}", text);
+    }
+
+    #[test]
+    fn copy_uses_the_selected_task_line() {
+        let mut app = app(&["(A) a due:2030-01-01", "b +p", "c @x"]);
+        draw(&mut app, 80, 20);
+        (app.focus, app.mode, app.selected) = (Focus::NoDue, InputMode::Focused, 1);
+        assert_eq!(app.selected_text().as_deref(), Some("c @x"));
+        (app.focus, app.selected) = (Focus::Due, 0);
+        assert_eq!(app.selected_text().as_deref(), Some("(A) a due:2030-01-01"));
+        app.selected = 5;
+        assert_eq!(app.selected_text(), None);
+    }
+
+    #[test]
+    fn compact_mode_renders_one_line_per_task() {
+        let mut app = app(&["(A) water +home @yard due:2030-01-02", "x sweep"]);
+        app.session.config.compact = true;
+        let today = NaiveDate::from_ymd_opt(2030, 1, 1).unwrap();
+        assert_eq!(app.task_item(0, 80, today).height(), 1);
+        let text = screen(&draw(&mut app, 100, 20));
+        assert!(text.contains(" 1 (A) water +home @yard due:2030-01-02"), "{}", text);
+        assert!(text.contains(" 2 x sweep"), "{}", text);
+    }
+
+    #[test]
+    fn space_toggles_the_selected_task() {
+        let mut app = app_on_file("toggle", &["a", "b"]);
+        draw(&mut app, 80, 20);
+        (app.focus, app.mode, app.selected) = (Focus::NoDue, InputMode::Focused, 1);
+        app.run_on_selected("toggle");
+        assert_eq!(app.error, None);
+        assert!(!app.todos[0].completion && app.todos[1].completion);
+        assert!(load_all(&app.session.file)[1].completion);
+        app.run_on_selected("toggle");
+        assert!(!load_all(&app.session.file)[1].completion);
+    }
+
+    #[test]
+    fn due_dates_can_show_the_weekday() {
+        let mut app = app(&["a due:2030-01-01"]);
+        assert!(!screen(&draw(&mut app, 100, 20)).contains("(Tue)"));
+        app.session.config.show_weekday = true;
+        let text = screen(&draw(&mut app, 100, 20));
+        assert!(text.contains("2030-01-01 (Tue)"), "{}", text);
+    }
+
+    #[test]
+    fn priority_keys_change_the_selected_task() {
+        let mut app = app_on_file("priority-keys", &["(B) a", "b"]);
+        draw(&mut app, 80, 20);
+        (app.focus, app.mode, app.selected) = (Focus::NoDue, InputMode::Focused, 0);
+        app.run_on_selected("up");
+        assert_eq!(app.error, None);
+        assert_eq!(app.todos[0].priority, Some('A'));
+        app.selected = 1;
+        app.run_on_selected("down");
+        assert_eq!(app.todos[1].priority, Some('C'));
+        let saved: Vec<Option<char>> = load_all(&app.session.file).iter().map(|t| t.priority).collect();
+        assert_eq!(saved, [Some('A'), Some('C')]);
+    }
+
+    #[test]
+    fn focus_moves_in_screen_order() {
+        let mut app = app(&[]);
+        let walk = |app: &mut App, delta, wrap| {
+            app.focus = Focus::Input;
+            (0..3).map(|_| { app.move_focus(delta, wrap); app.focus }).collect::<Vec<_>>()
+        };
+        assert_eq!(walk(&mut app, 1, false), [Focus::Due, Focus::NoDue, Focus::NoDue]);
+        assert_eq!(walk(&mut app, 1, true), [Focus::Due, Focus::NoDue, Focus::Input]);
+        app.session.config.nodue_left = true;
+        assert_eq!(walk(&mut app, 1, false), [Focus::NoDue, Focus::Due, Focus::Due]);
+        assert_eq!(walk(&mut app, -1, true), [Focus::Due, Focus::NoDue, Focus::Input]);
+        app.session.config.by_project = true;
+        assert_eq!(walk(&mut app, 1, true), [Focus::Due, Focus::Input, Focus::Due]);
+    }
+
+    #[test]
+    fn tag_menu_filters_by_the_chosen_tag() {
+        let mut app = app(&["a +work @desk", "b +home", "c +work"]);
+        app.input = "projects".into();
+        app.apply_command();
+        let menu = app.tag_menu.as_ref().unwrap();
+        assert_eq!(menu.tags, [("+home".to_string(), 1), ("+work".to_string(), 2)]);
+        assert_eq!(menu.command(), "filter +home");
+        app.on_tag_menu_key(KeyCode::Down);
+        assert_eq!(app.tag_menu.as_ref().unwrap().command(), "filter +work");
+        app.on_tag_menu_key(KeyCode::Enter);
+        assert!(app.tag_menu.is_none());
+        assert_eq!(app.view, [0, 2]);
+        app.input = "contexts".into();
+        app.apply_command();
+        assert_eq!(app.tag_menu.as_ref().unwrap().command(), "filter @desk");
+    }
+
+    #[test]
+    fn separators_change_the_lines_per_task() {
+        let mut app = app(&["a +p due:2030-01-01", "b due:2030-01-02"]);
+        let today = NaiveDate::from_ymd_opt(2030, 1, 1).unwrap();
+        let mut rows = Vec::new();
+        for (separator, height) in [(Separator::None, 3), (Separator::Blank, 4), (Separator::Line, 5)] {
+            app.session.config.separator = separator;
+            assert_eq!(app.task_item(0, 40, today).height(), height);
+            draw(&mut app, 80, 24);
+            rows.push(app.due_rows);
+        }
+        assert!(rows[0] > rows[1] && rows[1] > rows[2], "{:?}", rows);
+    }
+
+    #[test]
+    fn selection_scrolls_just_enough_to_stay_visible() {
+        let lines: Vec<String> = (1..=30).map(|n| format!("task {}", n)).collect();
+        let mut app = app(&lines.iter().map(String::as_str).collect::<Vec<_>>());
+        draw(&mut app, 80, 20);
+        (app.focus, app.mode) = (Focus::NoDue, InputMode::Focused);
+        let rows = app.nodue_rows;
+        // 最後に見えている行までは動かない
+        app.select_by(rows as isize - 1);
+        assert_eq!(app.nodue_scroll, 0);
+        // 1 行はみ出すと 1 行だけずれる
+        app.select_by(1);
+        assert_eq!((app.selected, app.nodue_scroll), (rows, 1));
+        app.select_by(5);
+        assert_eq!(app.nodue_scroll, 6);
+        // 上に戻ると選んだ行が先頭になる
+        app.select_by(-(rows as isize));
+        assert_eq!(app.nodue_scroll, app.selected);
+    }
+
+    #[test]
+    fn split_matches_marks_every_match() {
+        assert_eq!(split_matches("Buy milk, buy eggs", "buy", true), [("Buy", true), (" milk, ", false), ("buy", true), (" eggs", false)]);
+        assert_eq!(split_matches("Buy milk, buy eggs", "buy", false), [("Buy milk, ", false), ("buy", true), (" eggs", false)]);
+        assert_eq!(split_matches("aaa", "aa", false), [("aa", true), ("a", false)]);
+        assert_eq!(split_matches("Straße", "STRASSE", true), [("Straße", false)]);
+        assert_eq!(split_matches("ÄPFEL kaufen", "äpfel", true), [("ÄPFEL", true), (" kaufen", false)]);
+        assert_eq!(split_matches("milk", "", true), [("milk", false)]);
+        assert_eq!(split_matches("milk", "tea", true), [("milk", false)]);
+    }
+
+    #[test]
+    fn quitting_asks_only_with_unsaved_changes() {
+        let mut app = app(&["a"]);
+        assert!(app.request_quit());
+        assert!(!app.confirm_quit);
+        app.session.dirty = true;
+        assert!(!app.request_quit());
+        assert!(app.confirm_quit);
+        assert!(!app.on_confirm_quit_key(KeyCode::Char('x')));
+        assert!(app.confirm_quit);
+        assert!(!app.on_confirm_quit_key(KeyCode::Esc));
+        assert!(!app.confirm_quit);
+        app.request_quit();
+        assert!(app.on_confirm_quit_key(KeyCode::Char('y')));
+    }
+
+    #[test]
+    fn by_project_groups_rows_and_hides_collapsed_ones() {
+        let mut app = app(&["a +work", "b", "c +home +work", "d +home"]);
+        assert_eq!(project_groups(&app.todos, &app.view), [
+            (Some("home".to_string()), vec![2, 3]),
+            (Some("work".to_string()), vec![0, 2]),
+            (None, vec![1]),
+        ]);
+        app.session.config.by_project = true;
+        app.collapsed.insert(Some("work".into()));
+        assert_eq!(app.column_rows(Focus::Due), [
+            Row::Header(Some("home".into()), 2), Row::Task(2), Row::Task(3),
+            Row::Header(Some("work".into()), 2),
+            Row::Header(None, 1), Row::Task(1),
+        ]);
+        assert!(app.column_rows(Focus::NoDue).is_empty());
+    }
+
+    #[test]
+    fn completed_last_moves_done_tasks_down_in_order() {
+        let mut app = app(&["x a", "b due:2030-01-01", "c", "x d due:2030-01-02", "e", "x f"]);
+        assert_eq!(app.partition(), (vec![1, 3], vec![0, 2, 4, 5]));
+        app.session.config.completed_last = true;
+        assert_eq!(app.partition(), (vec![1, 3], vec![2, 4, 0, 5]));
+        app.session.grouped = true;
+        assert_eq!(app.partition(), (vec![1, 3], vec![0, 2, 4, 5]));
+    }
+
+    #[test]
+    fn clicks_are_ignored_while_a_popup_is_open() {
+        let click = |app: &mut App, x: u16, y: u16| {
+            app.on_mouse(MouseEvent { kind: MouseEventKind::Down(MouseButton::Left), column: x, row: y, modifiers: KeyModifiers::NONE });
+        };
+        let mut app = app(&["a +work", "b due:2030-01-01"]);
+        draw(&mut app, 80, 20);
+        let nodue = app.nodue_area;
+        let (x, y) = (nodue.x + 2, nodue.y + 2);
+        // タグメニュー
+        app.input = "projects".into();
+        app.apply_command();
+        click(&mut app, x, y);
+        assert!(app.tag_menu.is_some());
+        assert_eq!((app.focus, matches!(app.mode, InputMode::Normal)), (Focus::Input, true));
+        app.tag_menu = None;
+        // 入力中のカレンダー
+        (app.focus, app.mode) = (Focus::Input, InputMode::Editing);
+        type_text(&mut app, "add x");
+        app.open_picker();
+        click(&mut app, x, y);
+        assert!(app.picker.is_some());
+        assert_eq!((app.focus, app.input.as_str()), (Focus::Input, "add x"));
+        assert!(matches!(app.mode, InputMode::Editing));
+        // 閉じればクリックが効く
+        app.picker = None;
+        click(&mut app, x, y);
+        assert_eq!(app.focus, Focus::NoDue);
+        assert!(matches!(app.mode, InputMode::Focused));
+    }
+
+    #[test]
+    fn random_editing_keeps_the_cursor_in_bounds() {
+        let mut a = app(&[]);
+        (a.focus, a.mode) = (Focus::Input, InputMode::Editing);
+        // 再現できるように固定の種の xorshift で操作を選ぶ
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut next = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        let chars = ['a', ' ', '漢', '\u{301}', '👍', '\u{200d}', 'é'];
+        for step in 0..4000 {
+            match next(10) {
+                0..=4 => type_text(&mut a, &chars[next(chars.len() as u64) as usize].to_string()),
+                5 => a.on_editing_key(KeyCode::Backspace),
+                6 => a.on_editing_key(KeyCode::Left),
+                7 => a.on_editing_key(KeyCode::Right),
+                8 => a.on_editing_key(KeyCode::Home),
+                _ => a.on_editing_key(KeyCode::End),
+            }
+            assert!(a.cursor <= a.grapheme_count(), "step {}: cursor {} in {:?}", step, a.cursor, a.input);
+            if step % 100 == 0 {
+                draw(&mut a, 40, 12);
+            }
+        }
+        assert!(a.input.chars().count() > 200);
+        a.on_editing_key(KeyCode::End);
+        draw(&mut a, 40, 12);
+        a.on_editing_key(KeyCode::Home);
+        draw(&mut a, 40, 12);
+    }
+
+    #[test]
+    fn hidden_tasks_do_not_count_as_a_filter() {
+        let mut app = app(&["a due:2030-01-01", "b h:1 due:2030-01-02", "c", "d h:1"]);
+        let text = screen(&draw(&mut app, 100, 20));
+        assert!(text.contains("Due Todos (1)") && text.contains("No-Due Todos (1)"), "{}", text);
+        app.input = "show-hidden".into();
+        app.apply_command();
+        let text = screen(&draw(&mut app, 100, 20));
+        assert!(text.contains("Due Todos (2)") && text.contains("No-Due Todos (2)"), "{}", text);
+        app.input = "search b".into();
+        app.apply_command();
+        let text = screen(&draw(&mut app, 100, 20));
+        assert!(text.contains("Due Todos (1/2)") && text.contains("No-Due Todos (0/2)"), "{}", text);
+    }
+
+    #[test]
+    fn tag_menu_quotes_multi_word_projects() {
+        let mut app = app(&[r#"a +"Big Project""#, "b +small"]);
+        app.input = "projects".into();
+        app.apply_command();
+        assert_eq!(app.tag_menu.as_ref().unwrap().command(), r#"filter +"Big Project""#);
+        app.on_tag_menu_key(KeyCode::Enter);
+        assert_eq!(app.error, None);
+        assert_eq!(app.view, [0]);
+    }
+}
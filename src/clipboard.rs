@@ -0,0 +1,53 @@
+use std::io;
+#[cfg(feature = "clipboard")]
+use std::io::Write;
+#[cfg(feature = "clipboard")]
+use std::process::{Command, Stdio};
+
+/// 試すコピー用コマンド (先に見つかったものを使う)
+#[cfg(feature = "clipboard")]
+const COPY_COMMANDS: &[&[&str]] = if cfg!(target_os = "windows") {
+    &[&["clip"]]
+} else if cfg!(target_os = "macos") {
+    &[&["pbcopy"]]
+} else {
+    &[&["wl-copy"], &["xclip", "-selection", "clipboard"], &["xsel", "--clipboard", "--input"]]
+};
+
+/// `text` をシステムのクリップボードにコピー
+#[cfg(feature = "clipboard")]
+pub fn copy(text: &str) -> io::Result<()> {
+    let names: Vec<&str> = COPY_COMMANDS.iter().map(|c| c[0]).collect();
+    let mut last = io::Error::new(io::ErrorKind::NotFound, format!("no clipboard command found (tried {})", names.join(", ")));
+    for cmd in COPY_COMMANDS {
+        let spawned = Command::new(cmd[0])
+            .args(&cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            // 見つからなければ次の候補へ
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                last = e;
+                continue;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        return match child.wait()? {
+            status if status.success() => Ok(()),
+            status => Err(io::Error::other(format!("{} exited with {}", cmd[0], status))),
+        };
+    }
+    Err(last)
+}
+
+/// `clipboard` フィーチャーなしでビルドしたときは常に失敗
+#[cfg(not(feature = "clipboard"))]
+pub fn copy(_text: &str) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "built without the clipboard feature"))
+}
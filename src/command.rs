@@ -1,19 +1,268 @@
-use crate::storage::{append_one, load_all, rewrite_file};
-use crate::todo::Todo;
-use chrono::NaiveDate;
+use crate::config::Config;
+use crate::export::ExportFormat;
+use crate::import::parse_tasks;
+use crate::stats::{tag_counts, Stats};
+use crate::storage::{append_log, append_one, load_all, load_id_mark, load_views, lossy_lines, rewrite_file, save_id_mark, save_view, TODO_FILE, VIEWS_FILE};
+use crate::theme::Theme;
+use crate::todo::{normalize, parse_due, Bucket, Offset, Todo};
+use chrono::{Local, NaiveDate, NaiveTime};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 
 /// コマンドの種類
 pub enum Command {
-    List,
-    Add(String),
-    Done(usize),
-    Remove(usize),
-    Closest,
-    Important,
+    List(Option<usize>),
+    /// 本文と先頭に入れるか (`add-top`)
+    Add(String, bool),
+    Done(Vec<TaskRef>),
+    Toggle(Vec<TaskRef>),
+    Remove(Vec<TaskRef>),
+    DoneOverdue,
+    DoneMatch(String),
+    Clear { confirmed: bool },
+    Edit(TaskRef, String),
+    SetDue(TaskRef, Option<(NaiveDate, Option<NaiveTime>)>),
+    Open(TaskRef),
+    Duplicate(TaskRef),
+    BumpPriority(TaskRef, i8),
+    /// 作成日を今日にする
+    Touch(TaskRef),
+    /// 期日を先に延ばす (期日が無ければ今日から)
+    Snooze(TaskRef, Offset),
+    /// 期日の近さから優先度を付ける (`true` なら既にある優先度も付け直す)
+    AutoPriority(bool),
+    /// 2 つ目のタスクを 1 つ目にまとめて削除する
+    Merge(TaskRef, TaskRef),
+    SetNote(TaskRef, Option<String>),
+    Theme(String),
+    Compact(Option<bool>),
+    /// `+project` ごとにまとめた表示を切り替える
+    ByProject(Option<bool>),
+    /// 期日あり (`true`) / なしの列の並び順を変える
+    SortColumn(bool, ColumnSort),
+    ShowHidden,
+    Verify,
+    /// 読み書きする todo ファイルを切り替える
+    Use(PathBuf),
+    /// 件数と降順か
+    Closest(Option<usize>, bool),
+    Today,
+    Top,
+    When,
+    Important(Option<usize>, bool),
+    /// 期日と優先度をまとめた点数順
+    Smart(Option<usize>),
+    OnlyPriority(Option<usize>),
+    Due(DueRange),
+    Search(String),
+    Filter(Vec<String>),
+    Export(ExportFormat, PathBuf),
+    Import(PathBuf),
+    Write,
+    Deferred(bool),
+    Stats,
+    Projects,
+    Contexts,
+    SaveView(String),
+    LoadView(String),
     Empty,
+    /// 引数が不正 (エラーメッセージ)
+    Invalid(String),
     Unknown(String),
 }
 
+/// 候補として提示するコマンド名 (`(入力されそうな語, 実際のコマンド)`)
+const VERBS: &[(&str, &str)] = &[
+    ("list", "list"), ("add", "add"), ("add-top", "add-top"), ("done", "done"),
+    ("done-overdue", "done-overdue"), ("done-match", "done-match"), ("toggle", "toggle"),
+    ("clear", "clear"), ("rm", "rm"), ("remove", "rm"), ("delete", "rm"), ("edit", "edit"),
+    ("due-set", "due-set"), ("open", "open"), ("dup", "dup"), ("touch", "touch"),
+    ("merge", "merge"), ("auto-pri", "auto-pri"), ("snooze", "snooze"), ("up", "up"),
+    ("down", "down"), ("duplicate", "dup"), ("note", "note"), ("due", "due"), ("sd", "sd"),
+    ("today", "today"), ("top", "top"), ("when", "when"), ("search", "search"),
+    ("filter", "filter"), ("stats", "stats"), ("projects", "projects"), ("contexts", "contexts"),
+    ("export", "export"), ("import", "import"), ("w", "w"), ("write", "w"),
+    ("deferred", "deferred"), ("theme", "theme"), ("compact", "compact"),
+    ("by-project", "by-project"), ("show-hidden", "show-hidden"), ("sort", "sort"),
+    ("verify", "verify"), ("use", "use"), ("save", "save"), ("view", "view"), ("sp", "sp"),
+    ("smart", "smart"), ("only-pri", "only-pri"),
+];
+
+/// 本文が `text` で始まる未完了タスクを 1 件だけ探す
+/// (前方一致が無ければ部分一致で探す．0 件または複数件ならエラー)
+fn match_incomplete(todos: &[Todo], text: &str) -> Result<usize, String> {
+    let find = |pred: &dyn Fn(&str) -> bool| -> Vec<usize> {
+        (0..todos.len()).filter(|&i| !todos[i].completion && pred(&todos[i].description.content)).collect()
+    };
+    let mut found = find(&|c| c.starts_with(text));
+    if found.is_empty() {
+        found = find(&|c| c.contains(text));
+    }
+    match found[..] {
+        [idx] => Ok(idx),
+        [] => Err(format!("No incomplete task matches '{}'", text)),
+        _ => Err(format!("'{}' matches {} tasks; be more specific", text, found.len())),
+    }
+}
+
+/// `filter` に渡せる `+project` / `@context` か
+fn is_filter_tag(t: &str) -> bool {
+    t.len() > 1 && (t.starts_with('+') || t.starts_with('@'))
+}
+
+/// `filter` の引数をタグごとに分ける
+/// (`+"Big Project"` のように `+` / `@` の直後から `"` で囲んだ名前は空白を含めて 1 つにし，`"` を外す)
+fn split_filter_args(s: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        // `-` と `+` / `@` までを頭とする
+        let neg = if c == '-' { 1 } else { 0 };
+        let head = &rest[..neg + rest[neg..].chars().next().map_or(0, char::len_utf8)];
+        let body = &rest[head.len()..];
+        let (name, next) = match body.strip_prefix('"').and_then(|b| b.split_once('"')) {
+            Some(quoted) if head.ends_with(['+', '@']) => quoted,
+            _ => body.split_at(body.find(char::is_whitespace).unwrap_or(body.len())),
+        };
+        tags.push(format!("{}{}", head, name));
+        rest = next.trim_start();
+    }
+    tags
+}
+
+/// 編集距離 (レーベンシュタイン距離)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            cur.push((prev[j] + (ca != cb) as usize).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// 打ち間違いと思われる語に最も近いコマンドを返す (遠すぎれば `None`)
+fn suggest(word: &str) -> Option<&'static str> {
+    let limit = (word.chars().count() / 3).max(1);
+    VERBS.iter()
+        .map(|&(w, verb)| (edit_distance(word, w), verb))
+        .filter(|&(d, _)| d <= limit)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, verb)| verb)
+}
+
+/// タスクの指定方法
+#[derive(Clone, Copy)]
+pub enum TaskRef {
+    /// 表示中の番号 (1 始まり)
+    Position(usize),
+    /// `id:` タグの値 (`#42` と書く)
+    Id(u64),
+}
+
+impl TaskRef {
+    fn parse(s: &str) -> Option<Self> {
+        match s.strip_prefix('#') {
+            Some(id) => id.parse().ok().map(TaskRef::Id),
+            None => s.parse().ok().map(TaskRef::Position),
+        }
+    }
+
+    /// `todos` のインデックスに変換 (`view` が古く範囲外を指していれば `None`)
+    pub fn resolve(self, todos: &[Todo], view: &[usize]) -> Option<usize> {
+        match self {
+            TaskRef::Position(n) => n.checked_sub(1).and_then(|i| view.get(i)).copied().filter(|&i| i < todos.len()),
+            TaskRef::Id(id) => todos.iter().position(|t| t.id() == Some(id)),
+        }
+    }
+}
+
+impl fmt::Display for TaskRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskRef::Position(n) => write!(f, "{}", n),
+            TaskRef::Id(id) => write!(f, "#{}", id),
+        }
+    }
+}
+
+/// 列ごとの並び順
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ColumnSort {
+    /// `view` の順 (`sd` / `sp` などの結果のまま)
+    View,
+    /// 期日が近い順 (期日なしは末尾)
+    Due,
+    /// 優先度が高い順 (優先度なしは末尾)
+    Priority,
+}
+
+impl ColumnSort {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "view" => Some(ColumnSort::View),
+            "due" => Some(ColumnSort::Due),
+            "pri" => Some(ColumnSort::Priority),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ColumnSort::View => "view",
+            ColumnSort::Due => "due",
+            ColumnSort::Priority => "pri",
+        }
+    }
+
+    /// `view` 上の位置 `positions` をこの順に並べ替える (同順位は `view` の順を保つ)
+    pub fn apply(self, positions: &mut [usize], todos: &[Todo], view: &[usize]) {
+        let t = |pos: usize| &todos[view[pos]];
+        match self {
+            ColumnSort::View => {}
+            ColumnSort::Due => positions.sort_by_key(|&pos| {
+                let d = &t(pos).description;
+                (d.due.is_none(), d.due, d.due_time)
+            }),
+            ColumnSort::Priority => positions.sort_by_key(|&pos| (t(pos).priority.is_none(), t(pos).priority)),
+        }
+    }
+}
+
+/// `due` コマンドの期間指定
+pub enum DueRange {
+    Between(NaiveDate, NaiveDate),
+    Overdue,
+}
+
+impl DueRange {
+    /// `<start> <end>` / `<date>` / `overdue` をパース
+    fn parse(arg: &str) -> Option<Self> {
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok();
+        let args: Vec<&str> = arg.split_whitespace().collect();
+        match args.as_slice() {
+            ["overdue"] => Some(DueRange::Overdue),
+            [d] => date(d).map(|d| DueRange::Between(d, d)),
+            [s, e] => Some(DueRange::Between(date(s)?, date(e)?)),
+            _ => None,
+        }
+    }
+
+    /// 期日が範囲内か (両端を含む)
+    fn contains(&self, due: NaiveDate, today: NaiveDate) -> bool {
+        match *self {
+            DueRange::Between(start, end) => start <= due && due <= end,
+            DueRange::Overdue => due < today,
+        }
+    }
+}
+
 impl Command {
     pub fn parse(input: &str) -> Self {
         let cmd = input.trim();
@@ -22,81 +271,675 @@ impl Command {
         }
         let mut parts = cmd.splitn(2, ' ');
         match parts.next().unwrap() {
-            "list"      => Command::List,
-            "add"       => parts.next().map(|s| Command::Add(s.to_string())).unwrap_or(Command::Unknown(cmd.into())),
+            "list"      => parse_limit(parts.next()).map(Command::List).unwrap_or(Command::Unknown(cmd.into())),
+            "add"       => parts.next().map(|s| Command::Add(s.to_string(), false)).unwrap_or(Command::Unknown(cmd.into())),
+            "add-top"   => parts.next().map(|s| Command::Add(s.to_string(), true)).unwrap_or(Command::Unknown(cmd.into())),
             "done"      => parts.next()
-                                .and_then(|s| s.parse().ok())
+                                .and_then(parse_refs)
                                 .map(Command::Done)
                                 .unwrap_or(Command::Unknown(cmd.into())),
+            "toggle"    => parts.next()
+                                .and_then(parse_refs)
+                                .map(Command::Toggle)
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "done-overdue" => Command::DoneOverdue,
+            "done-match" => parts.next()
+                                .map(|s| s.trim())
+                                .filter(|s| !s.is_empty())
+                                .map(|s| Command::DoneMatch(s.to_string()))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "clear" => Command::Clear { confirmed: false },
+            "clear!" => Command::Clear { confirmed: true },
             "rm"    => parts.next()
-                                .and_then(|s| s.parse().ok())
+                                .and_then(parse_refs)
                                 .map(Command::Remove)
                                 .unwrap_or(Command::Unknown(cmd.into())),
-            "sd"   => Command::Closest,
-            "sp" => Command::Important,
+            "edit"      => parts.next()
+                                .and_then(|s| s.split_once(' '))
+                                .and_then(|(id, text)| Some(Command::Edit(TaskRef::parse(id)?, text.to_string())))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "due-set"   => parts.next()
+                                .and_then(|s| s.split_once(' '))
+                                .and_then(|(id, date)| {
+                                    let id = TaskRef::parse(id)?;
+                                    Some(match date.trim() {
+                                        "none" => Command::SetDue(id, None),
+                                        d => match parse_due(d) {
+                                            Some(due) => Command::SetDue(id, Some(due)),
+                                            None => Command::Invalid(format!("Invalid due date: {}", d)),
+                                        },
+                                    })
+                                })
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "up" | "down" => parts.next()
+                                .and_then(|s| TaskRef::parse(s.trim()))
+                                .map(|id| Command::BumpPriority(id, if cmd.starts_with("up") { -1 } else { 1 }))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "snooze"    => parts.next()
+                                .and_then(|s| s.split_once(' '))
+                                .and_then(|(id, offset)| Some(Command::Snooze(TaskRef::parse(id)?, Offset::parse(offset.trim())?)))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "auto-pri"  => match parts.next().map(str::trim) {
+                None => Command::AutoPriority(false),
+                Some("force") => Command::AutoPriority(true),
+                Some(_) => Command::Unknown(cmd.into()),
+            },
+            "merge"     => parts.next()
+                                .and_then(|s| s.split_once(' '))
+                                .and_then(|(a, b)| Some(Command::Merge(TaskRef::parse(a)?, TaskRef::parse(b.trim())?)))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "touch"     => parts.next()
+                                .and_then(|s| TaskRef::parse(s.trim()))
+                                .map(Command::Touch)
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "dup"       => parts.next()
+                                .and_then(|s| TaskRef::parse(s.trim()))
+                                .map(Command::Duplicate)
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "open"      => parts.next()
+                                .and_then(|s| TaskRef::parse(s.trim()))
+                                .map(Command::Open)
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "note"      => parts.next()
+                                .and_then(|s| {
+                                    let (id, text) = s.split_once(' ').unwrap_or((s, ""));
+                                    let text = text.trim();
+                                    Some(Command::SetNote(TaskRef::parse(id)?, (!text.is_empty()).then(|| text.to_string())))
+                                })
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "due"       => parts.next()
+                                .and_then(DueRange::parse)
+                                .map(Command::Due)
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "sd"   => parse_sort_args(parts.next()).map(|(n, desc)| Command::Closest(n, desc)).unwrap_or(Command::Unknown(cmd.into())),
+            "today" => Command::Today,
+            "top" => Command::Top,
+            "when" => Command::When,
+            "filter" => parts.next()
+                                .map(split_filter_args)
+                                .filter(|tags| !tags.is_empty() && tags.iter().all(|t| is_filter_tag(t.strip_prefix('-').unwrap_or(t))))
+                                .map(Command::Filter)
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "search" => parts.next()
+                                .map(|s| s.trim())
+                                .filter(|s| !s.is_empty())
+                                .map(|s| Command::Search(s.to_string()))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "stats" => Command::Stats,
+            "projects" => Command::Projects,
+            "contexts" => Command::Contexts,
+            "w" | "write" => Command::Write,
+            "deferred" => match parts.next().map(str::trim) {
+                Some("on") => Command::Deferred(true),
+                Some("off") => Command::Deferred(false),
+                _ => Command::Unknown(cmd.into()),
+            },
+            "import" => parts.next()
+                                .map(|s| s.trim())
+                                .filter(|s| !s.is_empty())
+                                .map(|s| Command::Import(s.into()))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "export"    => parts.next()
+                                .and_then(|s| s.split_once(' '))
+                                .filter(|(_, path)| !path.trim().is_empty())
+                                .and_then(|(format, path)| Some(Command::Export(ExportFormat::parse(format)?, path.trim().into())))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "compact" => match parts.next().map(str::trim) {
+                None => Command::Compact(None),
+                Some("on") => Command::Compact(Some(true)),
+                Some("off") => Command::Compact(Some(false)),
+                Some(_) => Command::Unknown(cmd.into()),
+            },
+            "by-project" => match parts.next().map(str::trim) {
+                None => Command::ByProject(None),
+                Some("on") => Command::ByProject(Some(true)),
+                Some("off") => Command::ByProject(Some(false)),
+                Some(_) => Command::Unknown(cmd.into()),
+            },
+            "show-hidden" => Command::ShowHidden,
+            "verify" => Command::Verify,
+            "use" => parts.next()
+                                .map(|s| s.trim())
+                                .filter(|s| !s.is_empty())
+                                .map(|s| Command::Use(s.into()))
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "sort" => parts.next()
+                                .and_then(|s| s.split_once(' '))
+                                .and_then(|(column, order)| {
+                                    let due = match column {
+                                        "due" => true,
+                                        "nodue" => false,
+                                        _ => return None,
+                                    };
+                                    Some(Command::SortColumn(due, ColumnSort::parse(order.trim())?))
+                                })
+                                .unwrap_or(Command::Unknown(cmd.into())),
+            "theme" => parts.next().map(|s| Command::Theme(s.trim().to_string())).unwrap_or(Command::Unknown(cmd.into())),
+            "save"  => parts.next().map(|s| Command::SaveView(s.trim().to_string())).unwrap_or(Command::Unknown(cmd.into())),
+            "view"  => parts.next().map(|s| Command::LoadView(s.trim().to_string())).unwrap_or(Command::Unknown(cmd.into())),
+            "sp" => parse_sort_args(parts.next()).map(|(n, desc)| Command::Important(n, desc)).unwrap_or(Command::Unknown(cmd.into())),
+            "smart" => parse_limit(parts.next()).map(Command::Smart).unwrap_or(Command::Unknown(cmd.into())),
+            "only-pri" => parse_limit(parts.next()).map(Command::OnlyPriority).unwrap_or(Command::Unknown(cmd.into())),
             other        => Command::Unknown(other.into()),
         }
     }
+
+    /// `view` を組み直すだけのコマンドか (保存済みビューとして再生できる)
+    pub fn is_view(&self) -> bool {
+        matches!(self, Command::List(_) | Command::Closest(..) | Command::Today | Command::Top | Command::When | Command::Important(..) | Command::Smart(_) | Command::OnlyPriority(_) | Command::Due(_) | Command::Search(_) | Command::Filter(_))
+    }
+
+    /// `todos` を書き換えて `view` を全件に戻すコマンドか
+    fn is_mutation(&self) -> bool {
+        matches!(self, Command::Add(..) | Command::Duplicate(_) | Command::Done(_) | Command::Toggle(_) | Command::DoneOverdue | Command::DoneMatch(_) | Command::Clear { .. } | Command::Remove(_) | Command::Edit(..) | Command::SetDue(..) | Command::SetNote(..) | Command::BumpPriority(..) | Command::Touch(_) | Command::Merge(..) | Command::AutoPriority(_) | Command::Snooze(..) | Command::Import(_))
+    }
+}
+
+/// コマンド間で引き継ぐ状態
+pub struct Session {
+    /// 現在の `view` を作ったコマンド (`save` で保存される)
+    pub last_view: Option<String>,
+    /// コマンドで切り替えられる設定
+    pub config: Config,
+    /// 保存していない変更があるか
+    pub dirty: bool,
+    /// 現在の `view` が `when` の区分ごとに並んでいるか
+    pub grouped: bool,
+    /// 書き込みを伴うコマンドを禁止する (`--readonly` / `--stdin`)
+    pub read_only: bool,
+    /// 一覧を標準入力から読んだ (読み直したり確かめたりする todo ファイルが無い)
+    pub from_stdin: bool,
+    /// `h:1` のタスクも全件の一覧に含める
+    pub show_hidden: bool,
+    /// 期日あり / なしの列の並び順
+    pub due_sort: ColumnSort,
+    pub nodue_sort: ColumnSort,
+    /// 読み書きする todo ファイル (`use` で切り替える)
+    pub file: PathBuf,
+    /// 現在の `view` を作った `search` の文字列 (一覧で強調する)
+    pub search: Option<String>,
+    /// 保存済みビューのファイル
+    pub views_file: PathBuf,
+}
+
+impl Session {
+    pub fn new(config: Config) -> Self {
+        Self { last_view: None, config, dirty: false, grouped: false, read_only: false, from_stdin: false, show_hidden: false, due_sort: ColumnSort::View, nodue_sort: ColumnSort::View, file: PathBuf::from(TODO_FILE), search: None, views_file: PathBuf::from(VIEWS_FILE) }
+    }
+}
+
+/// `[件数] [asc|desc]` をパース (順不同．降順なら `true`)
+fn parse_sort_args(arg: Option<&str>) -> Option<(Option<usize>, bool)> {
+    let (mut limit, mut desc) = (None, false);
+    for w in arg.unwrap_or_default().split_whitespace() {
+        match w {
+            "asc" => desc = false,
+            "desc" => desc = true,
+            n => limit = Some(n.parse().ok()?),
+        }
+    }
+    Some((limit, desc))
+}
+
+/// 件数指定の引数をパース (省略時は `Some(None)`，不正な値は `None`)
+fn parse_limit(arg: Option<&str>) -> Option<Option<usize>> {
+    match arg.map(str::trim) {
+        None | Some("") => Some(None),
+        Some(s) => s.parse().ok().map(Some),
+    }
+}
+
+/// 空白区切りの ID 列をパース (1 つでも解釈できなければ `None`)
+fn parse_refs(arg: &str) -> Option<Vec<TaskRef>> {
+    let refs: Vec<TaskRef> = arg.split_whitespace().map(TaskRef::parse).collect::<Option<_>>()?;
+    (!refs.is_empty()).then_some(refs)
+}
+
+/// ID 列を `todos` のインデックスに変換
+/// (見つからない ID はまとめてエラーメッセージにする)
+fn resolve_refs(todos: &[Todo], view: &[usize], refs: &[TaskRef]) -> (Vec<usize>, Option<String>) {
+    let mut found = Vec::new();
+    let mut invalid = Vec::new();
+    for &r in refs {
+        match r.resolve(todos, view) {
+            Some(idx) => found.push(idx),
+            None => invalid.push(r.to_string()),
+        }
+    }
+    let error = (!invalid.is_empty()).then(|| format!("Invalid ID: {}", invalid.join(", ")));
+    (found, error)
+}
+
+/// `id:` タグが無ければ既存の最大値と最後に振った値のどちらよりも大きい値を振る
+/// (消したタスクの id を使い回さないよう，最後に振った値を todo ファイルと同じ名前の `.id` に残す)
+fn assign_id(todo: &mut Todo, todos: &[Todo], session: &Session) {
+    if todo.id().is_none() {
+        let mark = session.file.with_extension("id");
+        let next = todos.iter().filter_map(Todo::id).max().unwrap_or(0).max(load_id_mark(&mark)) + 1;
+        todo.description.set_tag("id", Some(&next.to_string()));
+        let _ = save_id_mark(&mark, next);
+    }
+}
+
+/// OS 既定のブラウザで URL を開く (終了は待たない)
+fn open_url(url: &str) -> io::Result<()> {
+    let mut cmd = if cfg!(target_os = "windows") {
+        let mut c = process::Command::new("cmd");
+        c.args(["/C", "start", ""]);
+        c
+    } else if cfg!(target_os = "macos") {
+        process::Command::new("open")
+    } else {
+        process::Command::new("xdg-open")
+    };
+    let mut child = cmd.arg(url)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()?;
+    // ゾンビプロセスを残さないよう別スレッドで回収
+    std::thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// `view` を先頭 `limit` 件に制限 (件数を超える指定は無視)
+fn apply_limit(view: &mut Vec<usize>, limit: Option<usize>) {
+    if let Some(n) = limit {
+        view.truncate(n);
+    }
+}
+
+/// 書き込み失敗をヘッダー表示用の文字列に変換
+fn write_error(path: &Path, e: io::Error) -> String {
+    format!("Failed to write {}: {}", path.display(), e)
+}
+
+/// 全件の `view` (`h:1` のタスクは `show-hidden` にしない限り除く)
+pub fn default_view(todos: &[Todo], session: &Session) -> Vec<usize> {
+    (0..todos.len()).filter(|&i| session.show_hidden || !todos[i].description.hidden).collect()
+}
+
+/// `indices` のタスクを todo.txt の書式で
+fn task_lines(todos: &[Todo], indices: &[usize]) -> Vec<String> {
+    indices.iter().map(|&i| todos[i].format()).collect()
+}
+
+/// 変更したコマンドと対象のタスクを todo ファイルと同じ名前の `.log` に 1 タスク 1 行で追記
+/// (`日時<TAB>コマンド<TAB>タスク`．todo ファイルへの書き込みに失敗していれば (`error` があれば) 残さない．
+/// 履歴を書き込めなくてもコマンドは失敗させない)
+fn log_mutation(session: &Session, input: &str, tasks: &[String], error: &Option<String>) {
+    if error.is_some() {
+        return;
+    }
+    let now = Local::now().format("%Y-%m-%dT%H:%M:%S");
+    let lines: Vec<String> = tasks.iter().map(|t| format!("{}\t{}\t{}", now, input.trim(), t)).collect();
+    let _ = append_log(&session.file.with_extension("log"), &lines);
+}
+
+/// タスクを末尾に追加して `view` を全件に戻す (保存を遅らせる設定なら書き込まない)
+fn append_task(todos: &mut Vec<Todo>, view: &mut Vec<usize>, session: &mut Session, t: Todo) -> Option<String> {
+    if session.config.deferred_save {
+        session.dirty = true;
+    } else if let Err(e) = append_one(&session.file, &t) {
+        return Some(write_error(&session.file, e));
+    }
+    todos.push(t);
+    *view = default_view(todos, session);
+    None
+}
+
+/// 変更を保存して `view` を全件に戻す
+/// (保存を遅らせる設定なら未保存の印を付けるだけで書き込まない)
+fn save_changes(todos: &mut Vec<Todo>, view: &mut Vec<usize>, session: &mut Session) -> Option<String> {
+    if session.config.deferred_save {
+        session.dirty = true;
+        *view = default_view(todos, session);
+        return None;
+    }
+    let res = rewrite_file(&session.file, todos);
+    *todos = load_all(&session.file);
+    *view = default_view(todos, session);
+    res.err().map(|e| write_error(&session.file, e))
 }
 
 /// コマンド実行結果
 pub struct CommandResult {
     pub error: Option<String>,
+    /// エラーではない通知 (集計結果など)
+    pub message: Option<String>,
 }
 
 /// コマンドを実行して `todos` / `view` を更新
 pub fn execute_command(
     todos: &mut Vec<Todo>,
     view: &mut Vec<usize>,
+    session: &mut Session,
     input: &str,
 ) -> CommandResult {
     let cmd = Command::parse(input);
+    let (is_view, is_mutation, is_when) = (cmd.is_view(), cmd.is_mutation(), matches!(cmd, Command::When));
+    let search = if let Command::Search(text) = &cmd { Some(text.clone()) } else { None };
     let mut error = None;
+    let mut message = None;
+    if session.read_only && (is_mutation || matches!(cmd, Command::Write)) {
+        return CommandResult { error: Some("Read-only: this command would change the list".into()), message };
+    }
 
     match cmd {
         Command::Empty => {}
-        Command::List => {
-            *todos = load_all();
-            *view = (0..todos.len()).collect();
+        Command::List(limit) => {
+            // 未保存の変更があるときや標準入力から読んだときは読み直さない
+            if !session.dirty && !session.from_stdin {
+                *todos = load_all(&session.file);
+            }
+            *view = default_view(todos, session);
+            apply_limit(view, limit);
         }
-        Command::Add(text) => match Todo::from_add(&text) {
-            Ok(t) if append_one(&t).is_ok() => {
-                todos.push(t);
-                *view = (0..todos.len()).collect();
+        Command::Add(text, top) => match Todo::from_add(&text, session.config.default_priority) {
+            Ok(mut t) => {
+                assign_id(&mut t, todos, session);
+                let loose = session.config.loose_duplicates;
+                let key = t.duplicate_key(loose);
+                let duplicate = todos.iter().any(|o| !o.completion && o.duplicate_key(loose) == key);
+                let line = t.format();
+                error = if top {
+                    todos.insert(0, t);
+                    save_changes(todos, view, session)
+                } else {
+                    append_task(todos, view, session, t)
+                };
+                log_mutation(session, input, &[line], &error);
+                // 追加はできているのでエラーではなく通知にする
+                message = duplicate.then(|| "Duplicate task (added anyway)".into());
             }
-            Ok(_) => error = Some("Failed to append todo".into()),
             Err(e) => error = Some(e),
         },
-        Command::Done(id) => {
-            if let Some(&idx) = view.get(id.saturating_sub(1)) {
+        Command::Duplicate(id) => match id.resolve(todos, view) {
+            Some(idx) => {
+                let mut t = todos[idx].clone();
+                t.completion = false;
+                t.completion_date = None;
+                t.creation_date = Some(Local::now().date_naive());
+                t.raw = None;
+                t.description.set_tag("id", None);
+                assign_id(&mut t, todos, session);
+                message = Some(format!("Duplicated: {}", t.description.content));
+                let line = t.format();
+                error = append_task(todos, view, session, t);
+                log_mutation(session, input, &[line], &error);
+            }
+            None => error = Some("Invalid ID".into()),
+        },
+        Command::Done(ids) => {
+            let (indices, invalid) = resolve_refs(todos, view, &ids);
+            if !indices.is_empty() {
+                for &idx in &indices {
+                    todos[idx].mark_done();
+                }
+                let lines = task_lines(todos, &indices);
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            }
+            error = error.or(invalid);
+        }
+        Command::Toggle(ids) => {
+            let (indices, invalid) = resolve_refs(todos, view, &ids);
+            if !indices.is_empty() {
+                for &idx in &indices {
+                    todos[idx].toggle_done();
+                }
+                let lines = task_lines(todos, &indices);
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            }
+            error = error.or(invalid);
+        }
+        Command::DoneOverdue => {
+            let today = Local::now().date_naive();
+            let overdue: Vec<usize> = (0..todos.len())
+                .filter(|&i| todos[i].due_uncompleted().is_some_and(|d| d < today))
+                .collect();
+            if !overdue.is_empty() {
+                for &idx in &overdue {
+                    todos[idx].mark_done();
+                }
+                let lines = task_lines(todos, &overdue);
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            }
+            message = Some(format!("Completed {} overdue task(s)", overdue.len()));
+        }
+        Command::DoneMatch(text) => match match_incomplete(todos, &text) {
+            Ok(idx) => {
                 todos[idx].mark_done();
-                let _ = rewrite_file(todos);
-                *todos = load_all();
-                *view = (0..todos.len()).collect();
+                message = Some(format!("Completed: {}", todos[idx].description.content));
+                let lines = [todos[idx].format()];
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            }
+            Err(e) => error = Some(e),
+        },
+        Command::Clear { confirmed: false } => {
+            let n = todos.iter().filter(|t| t.completion).count();
+            error = Some(format!("This permanently deletes {} completed task(s); run 'clear!' to confirm", n));
+        }
+        Command::Clear { confirmed: true } => {
+            let before = todos.len();
+            let cleared: Vec<String> = todos.iter().filter(|t| t.completion).map(Todo::format).collect();
+            todos.retain(|t| !t.completion);
+            message = Some(format!("Cleared {} completed task(s)", before - todos.len()));
+            error = save_changes(todos, view, session);
+            log_mutation(session, input, &cleared, &error);
+        }
+        Command::Remove(ids) => {
+            let (mut indices, invalid) = resolve_refs(todos, view, &ids);
+            if !indices.is_empty() {
+                // 後ろから消して手前のインデックスをずらさない
+                indices.sort_unstable_by(|a, b| b.cmp(a));
+                indices.dedup();
+                let lines = task_lines(todos, &indices);
+                for idx in indices {
+                    todos.remove(idx);
+                }
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            }
+            error = error.or(invalid);
+        }
+        Command::Edit(id, text) => {
+            if let Some(idx) = id.resolve(todos, view) {
+                match Todo::from_edit(&text, &todos[idx]) {
+                    Ok(t) => {
+                        let lines = [todos[idx].format(), t.format()];
+                        todos[idx] = t;
+                        error = save_changes(todos, view, session);
+                        log_mutation(session, input, &lines, &error);
+                    }
+                    Err(e) => error = Some(e),
+                }
+            } else {
+                error = Some("Invalid ID".into());
+            }
+        }
+        Command::SetDue(id, due) => {
+            if let Some(idx) = id.resolve(todos, view) {
+                let d = &mut todos[idx].description;
+                d.due = due.map(|(d, _)| d);
+                d.due_time = due.and_then(|(_, t)| t);
+                // 解釈できずタグとして残っていた due も置き換える
+                d.set_tag("due", None);
+                let lines = [todos[idx].format()];
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            } else {
+                error = Some("Invalid ID".into());
+            }
+        }
+        Command::BumpPriority(id, delta) => {
+            if let Some(idx) = id.resolve(todos, view) {
+                todos[idx].bump_priority(delta);
+                message = todos[idx].priority.map(|p| format!("Priority: ({})", p));
+                let lines = [todos[idx].format()];
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            } else {
+                error = Some("Invalid ID".into());
+            }
+        }
+        Command::Touch(id) => {
+            if let Some(idx) = id.resolve(todos, view) {
+                todos[idx].creation_date = Some(Local::now().date_naive());
+                let lines = [todos[idx].format()];
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
             } else {
                 error = Some("Invalid ID".into());
             }
         }
-        Command::Remove(id) => {
-            if let Some(&idx) = view.get(id.saturating_sub(1)) {
-                todos.remove(idx);
-                let _ = rewrite_file(todos);
-                *todos = load_all();
-                *view = (0..todos.len()).collect();
+        Command::Snooze(id, offset) => match id.resolve(todos, view) {
+            Some(idx) => {
+                let d = &mut todos[idx].description;
+                match offset.add_to(d.due.unwrap_or_else(|| Local::now().date_naive())) {
+                    Some(due) => {
+                        d.due = Some(due);
+                        d.set_tag("due", None);
+                        message = Some(format!("Snoozed until {}", due.format("%Y-%m-%d")));
+                        let lines = [todos[idx].format()];
+                        error = save_changes(todos, view, session);
+                        log_mutation(session, input, &lines, &error);
+                    }
+                    None => error = Some("Invalid due date".into()),
+                }
+            }
+            None => error = Some("Invalid ID".into()),
+        },
+        Command::AutoPriority(force) => {
+            let today = Local::now().date_naive();
+            let targets: Vec<usize> = (0..todos.len())
+                .filter(|&i| !todos[i].completion && (force || todos[i].priority.is_none()))
+                .filter(|&i| {
+                    let p = todos[i].urgency(today).auto_priority();
+                    p.is_some() && p != todos[i].priority
+                })
+                .collect();
+            if !targets.is_empty() {
+                for &idx in &targets {
+                    todos[idx].priority = todos[idx].urgency(today).auto_priority();
+                }
+                let lines = task_lines(todos, &targets);
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            }
+            message = Some(format!("Set priority on {} task(s)", targets.len()));
+        }
+        Command::Merge(a, b) => match (a.resolve(todos, view), b.resolve(todos, view)) {
+            (Some(a), Some(b)) if a != b => {
+                let other = todos[b].clone();
+                let lines = [todos[a].format(), other.format()];
+                todos[a].merge(&other);
+                message = Some(format!("Merged: {}", todos[a].description.content));
+                todos.remove(b);
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
+            }
+            (Some(_), Some(_)) => error = Some("Cannot merge a task with itself".into()),
+            _ => error = Some("Invalid ID".into()),
+        },
+        Command::SetNote(id, note) => {
+            if let Some(idx) = id.resolve(todos, view) {
+                todos[idx].set_note(note.as_deref());
+                let lines = [todos[idx].format()];
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &lines, &error);
             } else {
                 error = Some("Invalid ID".into());
             }
         }
-        Command::Closest => {
+        Command::Open(id) => match id.resolve(todos, view).map(|idx| todos[idx].first_url()) {
+            Some(Some(url)) => match open_url(url) {
+                Ok(()) => message = Some(format!("Opening {}", url)),
+                Err(e) => error = Some(format!("Failed to open {}: {}", url, e)),
+            },
+            Some(None) => error = Some("No URL in this task".into()),
+            None => error = Some("Invalid ID".into()),
+        },
+        Command::Closest(limit, desc) => {
+            let mut pairs: Vec<(usize, NaiveDate)> = todos
+                .iter()
+                .enumerate()
+                .filter_map(|(i, t)| t.due_uncompleted().map(|d| (i, d)))
+                .collect();
+            pairs.sort_by_key(|&(_, d)| d);
+            if desc {
+                pairs.reverse();
+            }
+            *view = pairs.into_iter().map(|(i, _)| i).collect();
+            apply_limit(view, limit);
+        }
+        Command::Today => {
+            let today = Local::now().date_naive();
             let mut pairs: Vec<(usize, NaiveDate)> = todos
                 .iter()
                 .enumerate()
                 .filter_map(|(i, t)| t.due_uncompleted().map(|d| (i, d)))
+                .filter(|&(_, d)| d <= today)
                 .collect();
             pairs.sort_by_key(|&(_, d)| d);
             *view = pairs.into_iter().map(|(i, _)| i).collect();
         }
-        Command::Important => {
+        Command::Top => {
+            // 期日が近い順 (期日切れが先頭)，同じ期日なら優先度が高い順．期日なしはその後ろ
+            let top = (0..todos.len())
+                .filter(|&i| !todos[i].completion)
+                .min_by_key(|&i| {
+                    let (due, pri) = (todos[i].description.due, todos[i].priority);
+                    (due.is_none(), due, pri.is_none(), pri)
+                });
+            *view = top.into_iter().collect();
+            message = Some(match top {
+                Some(i) => format!("Top: {}", todos[i].description.content),
+                None => "No incomplete tasks".into(),
+            });
+        }
+        Command::When => {
+            let today = Local::now().date_naive();
+            let mut pairs: Vec<(usize, Bucket, NaiveDate)> = todos
+                .iter()
+                .enumerate()
+                .filter_map(|(i, t)| Some((i, t.bucket(today)?, t.description.due?)))
+                .collect();
+            pairs.sort_by_key(|&(_, b, d)| (b, d));
+            *view = pairs.into_iter().map(|(i, _, _)| i).collect();
+        }
+        Command::Important(limit, desc) => {
+            // 優先度なしは優先度ありの後ろに回す
+            let mut pairs: Vec<(usize, Option<char>)> = todos
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| !t.completion)
+                .map(|(i, t)| (i, t.priority))
+                .collect();
+            pairs.sort_by_key(|&(_, p)| (p.is_none(), p));
+            if desc {
+                pairs.reverse();
+            }
+            *view = pairs.into_iter().map(|(i, _)| i).collect();
+            apply_limit(view, limit);
+        }
+        Command::Smart(limit) => {
+            let today = Local::now().date_naive();
+            let (due_weight, priority_weight) = (session.config.smart_due_weight, session.config.smart_priority_weight);
+            let mut pairs: Vec<(usize, i64)> = todos
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| !t.completion)
+                .map(|(i, t)| (i, t.smart_score(today, due_weight, priority_weight)))
+                .collect();
+            pairs.sort_by_key(|&(_, score)| score);
+            *view = pairs.into_iter().map(|(i, _)| i).collect();
+            apply_limit(view, limit);
+        }
+        Command::OnlyPriority(limit) => {
             let mut pairs: Vec<(usize, char)> = todos
                 .iter()
                 .enumerate()
@@ -104,9 +947,793 @@ pub fn execute_command(
                 .collect();
             pairs.sort_by_key(|&(_, p)| p);
             *view = pairs.into_iter().map(|(i, _)| i).collect();
+            apply_limit(view, limit);
+        }
+        Command::Search(text) => {
+            let fold = session.config.fold_case;
+            let text = normalize(&text, fold);
+            *view = (0..todos.len())
+                .filter(|&i| normalize(&todos[i].description.content, fold).contains(&text))
+                .collect();
+        }
+        Command::Filter(tags) => {
+            let fold = session.config.fold_case;
+            *view = (0..todos.len())
+                .filter(|&i| tags.iter().all(|tag| match tag.strip_prefix('-') {
+                    // `-` 付きは除外条件
+                    Some(tag) => !todos[i].has_tag(tag, fold),
+                    None => todos[i].has_tag(tag, fold),
+                }))
+                .collect();
+        }
+        Command::Due(range) => {
+            let today = Local::now().date_naive();
+            let mut pairs: Vec<(usize, NaiveDate)> = todos
+                .iter()
+                .enumerate()
+                .filter_map(|(i, t)| t.due_uncompleted().map(|d| (i, d)))
+                .filter(|&(_, d)| range.contains(d, today))
+                .collect();
+            pairs.sort_by_key(|&(_, d)| d);
+            *view = pairs.into_iter().map(|(i, _)| i).collect();
+        }
+        Command::Stats => message = Some(Stats::compute(todos, Local::now().date_naive(), session.config.fold_case).to_string()),
+        Command::Export(format, path) => {
+            let tasks: Vec<&Todo> = view.iter().filter_map(|&i| todos.get(i)).collect();
+            match fs::write(&path, format.render(&tasks)) {
+                Ok(()) => message = Some(format!("Exported {} task(s) to {}", tasks.len(), path.display())),
+                Err(e) => error = Some(format!("Failed to export: {}", e)),
+            }
+        }
+        Command::Import(path) => match fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| parse_tasks(&path, &text)) {
+            Ok(imported) => {
+                let loose = session.config.loose_duplicates;
+                let mut keys: Vec<String> = todos.iter().map(|t| t.duplicate_key(loose)).collect();
+                let (mut added, mut skipped) = (0, 0);
+                for mut t in imported {
+                    let key = t.duplicate_key(loose);
+                    if keys.contains(&key) {
+                        skipped += 1;
+                        continue;
+                    }
+                    // 既存タスクと衝突する id は振り直す
+                    if t.id().is_some_and(|id| todos.iter().any(|o| o.id() == Some(id))) {
+                        t.description.set_tag("id", None);
+                    }
+                    assign_id(&mut t, todos, session);
+                    keys.push(key);
+                    todos.push(t);
+                    added += 1;
+                }
+                let new: Vec<String> = todos[todos.len() - added..].iter().map(Todo::format).collect();
+                error = save_changes(todos, view, session);
+                log_mutation(session, input, &new, &error);
+                message = Some(format!("Imported {} task(s), skipped {} duplicate(s)", added, skipped));
+            }
+            Err(e) => error = Some(format!("Failed to import {}: {}", path.display(), e)),
+        },
+        Command::Write => match rewrite_file(&session.file, todos) {
+            Ok(()) => {
+                session.dirty = false;
+                message = Some(format!("Saved {} task(s)", todos.len()));
+            }
+            Err(e) => error = Some(write_error(&session.file, e)),
+        },
+        Command::Deferred(on) => {
+            session.config.deferred_save = on;
+            // 即時保存に戻すときは溜まった変更を書き出す
+            if !on && session.dirty {
+                match rewrite_file(&session.file, todos) {
+                    Ok(()) => session.dirty = false,
+                    Err(e) => error = Some(write_error(&session.file, e)),
+                }
+            }
+            message = Some(if on { "Deferred save: on (run 'w' to save)".into() } else { "Deferred save: off".into() });
+        }
+        Command::Projects | Command::Contexts => {
+            let (sigil, label, counts) = if matches!(cmd, Command::Projects) {
+                ('+', "projects", tag_counts(todos, |t| &t.description.projects, session.config.fold_case))
+            } else {
+                ('@', "contexts", tag_counts(todos, |t| &t.description.contexts, session.config.fold_case))
+            };
+            message = Some(if counts.is_empty() {
+                format!("No {}", label)
+            } else {
+                let list: Vec<String> = counts.iter().map(|(tag, n)| format!("{}{} ({})", sigil, tag, n)).collect();
+                list.join(", ")
+            });
+        }
+        Command::SaveView(name) => {
+            let command = session.last_view.as_deref().unwrap_or("list");
+            match save_view(&session.views_file, &name, command) {
+                Ok(()) => message = Some(format!("Saved view '{}': {}", name, command)),
+                Err(e) => error = Some(format!("Failed to save view: {}", e)),
+            }
+        }
+        Command::LoadView(name) => match load_views(&session.views_file).get(&name) {
+            // 別のビューを呼ぶビューは循環しうるので実行しない
+            Some(command) if matches!(Command::parse(command), Command::LoadView(_)) => {
+                error = Some(format!("View '{}' refers to another view", name));
+            }
+            Some(command) => {
+                let res = execute_command(todos, view, session, command);
+                error = res.error;
+                message = res.message;
+            }
+            None => error = Some(format!("No saved view: {}", name)),
+        },
+        Command::Theme(name) => match Theme::by_name(&name) {
+            Some(theme) => {
+                session.config.theme = theme;
+                message = Some(format!("Theme: {}", theme.name));
+            }
+            None => {
+                let names: Vec<&str> = Theme::ALL.iter().map(|t| t.name).collect();
+                error = Some(format!("Unknown theme '{}' (available: {})", name, names.join(", ")));
+            }
+        },
+        Command::Compact(on) => {
+            let config = &mut session.config;
+            config.compact = on.unwrap_or(!config.compact);
+            message = Some(format!("Compact view: {}", if config.compact { "on" } else { "off" }));
+        }
+        Command::ByProject(on) => {
+            let config = &mut session.config;
+            config.by_project = on.unwrap_or(!config.by_project);
+            message = Some(format!("Group by project: {}", if config.by_project { "on" } else { "off" }));
+        }
+        Command::SortColumn(due, order) => {
+            let (name, sort) = if due { ("Due", &mut session.due_sort) } else { ("No-Due", &mut session.nodue_sort) };
+            *sort = order;
+            message = Some(format!("{} column sorted by {}", name, order.name()));
+        }
+        Command::Verify if session.from_stdin => error = Some("verify checks todo.txt and is not available for standard input".into()),
+        Command::Verify => match fs::read_to_string(&session.file) {
+            Ok(txt) => match lossy_lines(&txt).as_slice() {
+                [] => message = Some("All lines round-trip".into()),
+                lines => {
+                    let lines: Vec<String> = lines.iter().map(usize::to_string).collect();
+                    error = Some(format!("Lines that change when re-saved: {}", lines.join(", ")));
+                }
+            },
+            Err(e) => error = Some(format!("Failed to read {}: {}", session.file.display(), e)),
+        },
+        Command::Use(path) => {
+            // 切り替える前に未保存の変更を今のファイルに書き込む
+            let flushed = if session.dirty { rewrite_file(&session.file, todos) } else { Ok(()) };
+            match flushed {
+                Ok(()) => {
+                    *todos = load_all(&path);
+                    session.file = path;
+                    session.dirty = false;
+                    session.grouped = false;
+                    session.last_view = None;
+                    session.search = None;
+                    *view = default_view(todos, session);
+                    message = Some(format!("Using {} ({} task(s))", session.file.display(), todos.len()));
+                }
+                Err(e) => error = Some(write_error(&session.file, e)),
+            }
+        }
+        Command::ShowHidden => {
+            session.show_hidden = !session.show_hidden;
+            *view = default_view(todos, session);
+            session.grouped = false;
+            session.last_view = None;
+            session.search = None;
+            message = Some(format!("Hidden tasks: {}", if session.show_hidden { "shown" } else { "hidden" }));
+        }
+        Command::Invalid(e) => error = Some(e),
+        Command::Unknown(s) => {
+            let word = s.split_whitespace().next().unwrap_or_default();
+            error = Some(match suggest(word) {
+                Some(verb) if verb != word => format!("Unknown command '{}' — did you mean '{}'?", s, verb),
+                _ => format!("Unknown command: {}", s),
+            });
         }
-        Command::Unknown(s) => error = Some(format!("Unknown command: {}", s)),
     }
 
-    CommandResult { error }
-}
\ No newline at end of file
+    if error.is_none() {
+        if is_view {
+            session.last_view = Some(input.trim().to_string());
+        } else if is_mutation {
+            session.last_view = None;
+        }
+        if is_view || is_mutation {
+            session.grouped = is_when;
+            session.search = search;
+        }
+    }
+
+    CommandResult { error, message }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一時ディレクトリの todo ファイルを使うコマンドの実行環境
+    struct Fixture {
+        todos: Vec<Todo>,
+        view: Vec<usize>,
+        session: Session,
+    }
+
+    impl Fixture {
+        /// `lines` を書いた todo ファイルを読み込む (`name` はテストごとに変える)
+        fn new(name: &str, lines: &[&str]) -> Self {
+            let dir = std::env::temp_dir().join(format!("todo-test-{}-{}", process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let mut session = Session::new(Config::default());
+            session.file = dir.join("todo.txt");
+            session.views_file = dir.join("todo.views");
+            fs::write(&session.file, lines.iter().map(|l| format!("{}\n", l)).collect::<String>()).unwrap();
+            let todos = load_all(&session.file);
+            let view = default_view(&todos, &session);
+            Self { todos, view, session }
+        }
+
+        fn run(&mut self, input: &str) -> CommandResult {
+            execute_command(&mut self.todos, &mut self.view, &mut self.session, input)
+        }
+
+        /// 表示中の todo の本文
+        fn shown(&self) -> Vec<&str> {
+            self.view.iter().map(|&i| self.todos[i].description.content.as_str()).collect()
+        }
+    }
+
+    /// 今日から `offset` 日後の日付 (`YYYY-MM-DD`)
+    fn day(offset: i64) -> String {
+        (Local::now().date_naive() + chrono::Duration::days(offset)).format("%Y-%m-%d").to_string()
+    }
+
+    #[test]
+    fn ids_of_removed_tasks_are_not_reused() {
+        let mut f = Fixture::new("ids-not-reused", &[]);
+        f.run("add a");
+        f.run("add b");
+        assert!(f.run("rm #2").error.is_none());
+        f.run("add c");
+        let c = f.todos.iter().find(|t| t.description.content == "c").unwrap();
+        assert_eq!(c.id(), Some(3));
+        assert!(f.run("done #2").error.is_some());
+    }
+
+    #[test]
+    fn done_writes_an_audit_entry() {
+        let mut f = Fixture::new("audit", &["a", "b"]);
+        assert!(f.run("done 2").error.is_none());
+        let log = fs::read_to_string(f.session.file.with_extension("log")).unwrap();
+        let fields: Vec<&str> = log.lines().next().unwrap().split('\t').collect();
+        assert_eq!(fields[1..], ["done 2", &f.todos[1].format()]);
+    }
+
+    #[test]
+    fn failed_write_is_not_audited() {
+        let mut f = Fixture::new("audit-failed", &["a"]);
+        // todo ファイルの場所をディレクトリにして書き込みを失敗させる
+        fs::remove_file(&f.session.file).unwrap();
+        fs::create_dir(&f.session.file).unwrap();
+        assert!(f.run("done 1").error.is_some());
+        assert!(!f.session.file.with_extension("log").exists());
+    }
+
+    #[test]
+    fn due_set_reports_invalid_date() {
+        let mut f = Fixture::new("due-set-invalid", &["a"]);
+        assert_eq!(f.run("due-set 1 2024-13-40").error.as_deref(), Some("Invalid due date: 2024-13-40"));
+        assert_eq!(f.todos[0].description.due, None);
+    }
+
+    #[test]
+    fn duplicate_add_is_a_message_not_an_error() {
+        let mut f = Fixture::new("duplicate", &["(A) water plants due:2030-01-01"]);
+        let res = f.run("add water plants");
+        assert!(res.error.is_none());
+        assert_eq!(res.message.as_deref(), Some("Duplicate task (added anyway)"));
+        assert_eq!(f.todos.len(), 2);
+    }
+
+    #[test]
+    fn strict_duplicates_compare_priority_and_due() {
+        let mut f = Fixture::new("duplicate-strict", &["(A) water plants due:2030-01-01"]);
+        f.session.config.loose_duplicates = false;
+        assert!(f.run("add water plants").message.is_none());
+        assert!(f.run("add (A) water plants due:2030-01-01").message.is_some());
+    }
+
+    #[test]
+    fn read_only_rejects_mutations_but_lists_and_verifies() {
+        let mut f = Fixture::new("read-only", &["a", "b"]);
+        f.session.read_only = true;
+        for input in ["add c", "done 1", "rm 1", "edit 1 x", "clear!", "w"] {
+            assert!(f.run(input).error.is_some_and(|e| e.starts_with("Read-only")), "{}", input);
+        }
+        assert_eq!(load_all(&f.session.file).len(), 2);
+        // `--readonly` でも todo ファイルは読み直す
+        fs::write(&f.session.file, "a\nb\nc\n").unwrap();
+        let res = f.run("list");
+        assert!(res.error.is_none());
+        assert_eq!(f.view.len(), 3);
+        assert!(f.run("verify").error.is_none());
+    }
+
+    #[test]
+    fn stdin_list_is_not_reloaded() {
+        let mut f = Fixture::new("stdin", &[]);
+        (f.session.read_only, f.session.from_stdin) = (true, true);
+        f.todos = vec![Todo::parse("a")];
+        assert!(f.run("list").error.is_none());
+        assert_eq!(f.view, [0]);
+        assert!(f.run("verify").error.is_some());
+    }
+
+    #[test]
+    fn limit_caps_list_and_sorts() {
+        let lines = ["(B) b due:2030-01-02", "(A) a due:2030-01-01", "(C) c due:2030-01-03"];
+        let mut f = Fixture::new("limit", &lines);
+        for (limit, expected) in [(2, 2), (3, 3), (10, 3)] {
+            for verb in ["list", "sd", "sp"] {
+                assert!(f.run(&format!("{} {}", verb, limit)).error.is_none());
+                assert_eq!(f.view.len(), expected, "{} {}", verb, limit);
+            }
+        }
+        f.run("sd 2");
+        assert_eq!(f.shown(), ["a", "b"]);
+        f.run("sp 1");
+        assert_eq!(f.shown(), ["a"]);
+        f.run("list 2");
+        assert_eq!(f.shown(), ["b", "a"]);
+    }
+
+    #[test]
+    fn unwritable_file_reports_error() {
+        for input in ["add b", "done 1", "rm 1"] {
+            let mut f = Fixture::new("unwritable", &[]);
+            f.todos = vec![Todo::parse("a")];
+            f.view = vec![0];
+            f.session.file = f.session.file.with_file_name("missing-dir").join("todo.txt");
+            assert!(f.run(input).error.is_some_and(|e| e.starts_with("Failed to write")), "{}", input);
+        }
+    }
+
+    #[test]
+    fn due_range_single_day_and_overdue() {
+        let lines = ["a due:2000-01-01", "b due:2999-06-01", "c due:2999-06-03", "d due:2999-06-08", "x e due:2999-06-03", "f"];
+        let mut f = Fixture::new("due-range", &lines);
+        f.run("due 2999-06-01 2999-06-07");
+        assert_eq!(f.shown(), ["b", "c"]);
+        f.run("due 2999-06-03");
+        assert_eq!(f.shown(), ["c"]);
+        f.run("due overdue");
+        assert_eq!(f.shown(), ["a"]);
+        assert!(f.run("due 2999-13-01").error.is_some());
+    }
+
+    #[test]
+    fn today_shows_overdue_then_today() {
+        let lines = [
+            format!("future due:{}", day(1)),
+            format!("today due:{}", day(0)),
+            format!("old due:{}", day(-5)),
+            format!("yesterday due:{}", day(-1)),
+            format!("x done due:{}", day(-2)),
+            "no due".to_string(),
+        ];
+        let mut f = Fixture::new("today", &lines.iter().map(String::as_str).collect::<Vec<_>>());
+        assert!(f.run("today").error.is_none());
+        assert_eq!(f.shown(), ["old", "yesterday", "today"]);
+    }
+
+    #[test]
+    fn remove_several_ids_at_once() {
+        let mut f = Fixture::new("remove-batch", &["a", "b", "c", "d", "e"]);
+        assert!(f.run("rm 1 3").error.is_none());
+        assert_eq!(f.shown(), ["b", "d", "e"]);
+        assert_eq!(load_all(&f.session.file).len(), 3);
+        let res = f.run("rm 2 9 10");
+        assert_eq!(res.error.as_deref(), Some("Invalid ID: 9, 10"));
+        assert_eq!(f.shown(), ["b", "e"]);
+    }
+
+    #[test]
+    fn saved_view_replays_its_command() {
+        let mut f = Fixture::new("views", &["b due:2030-01-02", "a due:2030-01-01", "c"]);
+        f.run("sd");
+        assert!(f.run("save soon").error.is_none());
+        f.run("list");
+        assert!(f.run("view soon").error.is_none());
+        assert_eq!(f.shown(), ["a", "b"]);
+        assert!(f.run("view missing").error.is_some());
+    }
+
+    #[test]
+    fn done_overdue_marks_only_overdue_tasks() {
+        let lines = [
+            format!("late1 due:{}", day(-3)),
+            format!("future due:{}", day(3)),
+            format!("late2 due:{}", day(-1)),
+            format!("x 2024-01-01 finished due:{}", day(-5)),
+        ];
+        let mut f = Fixture::new("done-overdue", &lines.iter().map(String::as_str).collect::<Vec<_>>());
+        let res = f.run("done-overdue");
+        assert_eq!(res.message.as_deref(), Some("Completed 2 overdue task(s)"));
+        let done: Vec<bool> = load_all(&f.session.file).iter().map(|t| t.completion).collect();
+        assert_eq!(done, [true, false, true, true]);
+        assert_eq!(f.todos[3].completion_date, NaiveDate::from_ymd_opt(2024, 1, 1));
+    }
+
+    #[test]
+    fn clear_keeps_only_incomplete_tasks() {
+        let mut f = Fixture::new("clear", &["x a", "b", "x (A) c", "d"]);
+        let res = f.run("clear");
+        assert!(res.error.is_some_and(|e| e.contains("2 completed")));
+        assert_eq!(f.todos.len(), 4);
+        let res = f.run("clear!");
+        assert_eq!(res.message.as_deref(), Some("Cleared 2 completed task(s)"));
+        assert_eq!(f.shown(), ["b", "d"]);
+        assert!(load_all(&f.session.file).iter().all(|t| !t.completion));
+    }
+
+    #[test]
+    fn task_ref_resolves_positions_and_ids() {
+        let todos: Vec<Todo> = ["a id:7", "b id:42", "c"].iter().map(|l| Todo::parse(l)).collect();
+        let view = [2, 1];
+        assert_eq!(TaskRef::parse("1").unwrap().resolve(&todos, &view), Some(2));
+        assert_eq!(TaskRef::parse("2").unwrap().resolve(&todos, &view), Some(1));
+        assert_eq!(TaskRef::parse("3").unwrap().resolve(&todos, &view), None);
+        assert_eq!(TaskRef::parse("0").unwrap().resolve(&todos, &view), None);
+        // `#id` は表示順に関係なく同じタスク
+        assert_eq!(TaskRef::parse("#7").unwrap().resolve(&todos, &view), Some(0));
+        assert_eq!(TaskRef::parse("#42").unwrap().resolve(&todos, &[]), Some(1));
+        assert_eq!(TaskRef::parse("#9").unwrap().resolve(&todos, &view), None);
+        assert!(TaskRef::parse("#x").is_none());
+    }
+
+    #[test]
+    fn sp_puts_unprioritized_tasks_last() {
+        let mut f = Fixture::new("sp", &["none1", "(C) c", "x (A) done", "(A) a", "none2"]);
+        f.run("sp");
+        assert_eq!(f.shown(), ["a", "c", "none1", "none2"]);
+        f.run("only-pri");
+        assert_eq!(f.shown(), ["a", "c"]);
+    }
+
+    #[test]
+    fn due_set_sets_and_clears_due() {
+        let mut f = Fixture::new("due-set", &["a due:bogus", "b"]);
+        assert!(f.run("due-set 2 2024-06-01").error.is_none());
+        assert_eq!(f.todos[1].description.due, NaiveDate::from_ymd_opt(2024, 6, 1));
+        assert_eq!(f.todos[1].format(), "b due:2024-06-01");
+        // 解釈できなかった due タグも置き換える
+        assert!(f.run("due-set 1 2024-06-02T10:00").error.is_none());
+        assert_eq!(f.todos[0].format(), "a due:2024-06-02T10:00");
+        assert!(f.run("due-set 1 none").error.is_none());
+        assert_eq!(f.todos[0].format(), "a");
+        assert_eq!(load_all(&f.session.file)[0].description.due, None);
+    }
+
+    #[test]
+    fn completing_one_task_keeps_the_others_intact() {
+        let lines = [
+            "(A) 2024-01-02 call mom +family @phone due:2024-06-01",
+            "buy milk ref:ABC note:\"see email\" id:12",
+            "table  1  vs  2 due:2024-06-01T09:30",
+            "meet +\"Big Project\" @\"at home\" t:2024-06-01",
+        ];
+        let mut f = Fixture::new("fidelity", &lines);
+        let before = f.todos.clone();
+        assert!(f.run("done 2").error.is_none());
+        let after = load_all(&f.session.file);
+        for i in [0, 2, 3] {
+            assert_eq!(after[i], before[i]);
+        }
+        assert!(after[1].completion);
+    }
+
+    #[test]
+    fn suggest_close_verbs_only() {
+        assert_eq!(suggest("remvoe"), Some("rm"));
+        assert_eq!(suggest("lisst"), Some("list"));
+        assert_eq!(suggest("frobnicate"), None);
+        let res = Fixture::new("suggest", &[]).run("remvoe 1");
+        assert_eq!(res.error.as_deref(), Some("Unknown command 'remvoe' — did you mean 'rm'?"));
+    }
+
+    #[test]
+    fn note_command_sets_and_clears() {
+        let mut f = Fixture::new("note", &["call bob"]);
+        assert!(f.run("note 1 ask about invoice").error.is_none());
+        assert_eq!(load_all(&f.session.file)[0].note(), Some("ask about invoice"));
+        assert!(f.run("note 1").error.is_none());
+        assert_eq!(load_all(&f.session.file)[0].note(), None);
+    }
+
+    #[test]
+    fn theme_switches_the_session_theme() {
+        let mut f = Fixture::new("theme", &[]);
+        assert_eq!(f.run("theme monochrome").message.as_deref(), Some("Theme: monochrome"));
+        assert_eq!(f.session.config.theme.name, "monochrome");
+        assert!(f.run("theme neon").error.is_some_and(|e| e.starts_with("Unknown theme 'neon'")));
+        assert_eq!(f.session.config.theme.name, "monochrome");
+    }
+
+    #[test]
+    fn match_incomplete_finds_one_task_by_text() {
+        let todos: Vec<Todo> = ["buy milk", "x buy bread", "call mom", "call dad"].iter().map(|l| Todo::parse(l)).collect();
+        assert_eq!(match_incomplete(&todos, "buy"), Ok(0));
+        assert_eq!(match_incomplete(&todos, "mom"), Ok(2));
+        assert_eq!(match_incomplete(&todos, "bread"), Err("No incomplete task matches 'bread'".to_string()));
+        assert_eq!(match_incomplete(&todos, "call"), Err("'call' matches 2 tasks; be more specific".to_string()));
+    }
+
+    #[test]
+    fn completing_a_task_keeps_other_lines_verbatim() {
+        let odd = "(A)  spaced   out  +p due:2030-1-2";
+        let mut f = Fixture::new("raw-preserved", &[odd, "b"]);
+        assert!(f.run("done 2").error.is_none());
+        let text = fs::read_to_string(&f.session.file).unwrap();
+        assert_eq!(text.lines().next(), Some(odd));
+        assert!(text.lines().nth(1).unwrap().starts_with("x "));
+    }
+
+    #[test]
+    fn import_adds_tasks_from_a_file() {
+        let mut f = Fixture::new("import", &["a"]);
+        let path = f.session.file.with_file_name("more.txt");
+        fs::write(&path, "(B) b +p\nc due:2030-01-01\na\n").unwrap();
+        let res = f.run(&format!("import {}", path.display()));
+        assert!(res.error.is_none(), "{:?}", res.error);
+        assert_eq!(res.message.as_deref(), Some("Imported 2 task(s), skipped 1 duplicate(s)"));
+        let saved = load_all(&f.session.file);
+        let contents: Vec<&str> = saved.iter().map(|t| t.description.content.as_str()).collect();
+        assert_eq!(contents, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn deferred_changes_wait_for_write() {
+        let mut f = Fixture::new("deferred", &["a"]);
+        f.run("deferred on");
+        f.run("add b");
+        f.run("done 1");
+        assert!(f.session.dirty);
+        assert_eq!(fs::read_to_string(&f.session.file).unwrap(), "a\n");
+        assert_eq!(f.run("w").message.as_deref(), Some("Saved 2 task(s)"));
+        assert!(!f.session.dirty);
+        let saved = load_all(&f.session.file);
+        assert!(saved[0].completion);
+        assert_eq!(saved[1].description.content, "b");
+    }
+
+    #[test]
+    fn filter_folds_case_of_tags() {
+        let mut f = Fixture::new("filter-case", &["a +Work", "b +work", "c +home"]);
+        f.run("filter +work");
+        assert_eq!(f.shown(), ["a", "b"]);
+        f.session.config.fold_case = false;
+        f.run("filter +work");
+        assert_eq!(f.shown(), ["b"]);
+    }
+
+    #[test]
+    fn top_picks_nearest_due_then_priority() {
+        let lines = ["(A) later due:2030-02-01", "(C) soon due:2030-01-01", "(B) sooner due:2030-01-01", "x (A) done due:2029-01-01", "(A) no due"];
+        let mut f = Fixture::new("top", &lines);
+        assert_eq!(f.run("top").message.as_deref(), Some("Top: sooner"));
+        assert_eq!(f.view, [2]);
+        let mut f = Fixture::new("top-nodue", &["b", "(A) a", "x done"]);
+        assert_eq!(f.run("top").message.as_deref(), Some("Top: a"));
+        let mut f = Fixture::new("top-empty", &["x done"]);
+        assert_eq!(f.run("top").message.as_deref(), Some("No incomplete tasks"));
+        assert!(f.view.is_empty());
+    }
+
+    #[test]
+    fn dup_of_a_completed_task_is_a_fresh_open_task() {
+        let mut f = Fixture::new("dup", &["x (A) 2024-01-02 2024-01-01 water plants +home id:7"]);
+        let res = f.run("dup 1");
+        assert_eq!(res.message.as_deref(), Some("Duplicated: water plants"));
+        let copy = &f.todos[1];
+        assert!(!copy.completion);
+        assert_eq!(copy.completion_date, None);
+        assert_eq!(copy.creation_date, Some(Local::now().date_naive()));
+        assert_eq!(copy.priority, Some('A'));
+        assert_eq!(copy.description.projects, ["home"]);
+        assert_eq!(copy.id(), Some(8));
+        assert!(f.todos[0].completion);
+    }
+
+    #[test]
+    fn filter_combines_required_and_excluded_tags() {
+        let mut f = Fixture::new("filter-negative", &["a +work @office", "b +work @home", "c +home", "d"]);
+        f.run("filter -+work");
+        assert_eq!(f.shown(), ["c", "d"]);
+        f.run("filter +work -@home");
+        assert_eq!(f.shown(), ["a"]);
+        f.run("filter -+work -+home");
+        assert_eq!(f.shown(), ["d"]);
+    }
+
+    #[test]
+    fn hidden_tasks_appear_only_after_show_hidden() {
+        let mut f = Fixture::new("hidden", &["a", "b h:1", "c"]);
+        assert_eq!(f.shown(), ["a", "c"]);
+        assert_eq!(f.run("show-hidden").message.as_deref(), Some("Hidden tasks: shown"));
+        assert_eq!(f.shown(), ["a", "b", "c"]);
+        assert_eq!(f.run("show-hidden").message.as_deref(), Some("Hidden tasks: hidden"));
+        assert_eq!(f.shown(), ["a", "c"]);
+    }
+
+    #[test]
+    fn column_sort_orders_positions_stably() {
+        let todos: Vec<Todo> = ["(B) a due:2030-01-03", "b due:2030-01-01T12:00", "(A) c", "(B) d due:2030-01-01T09:00", "e"]
+            .iter().map(|l| Todo::parse(l)).collect();
+        // `view` は逆順．位置はその上の添字
+        let view = [4, 3, 2, 1, 0];
+        let sorted = |sort: ColumnSort| {
+            let mut positions: Vec<usize> = (0..view.len()).collect();
+            sort.apply(&mut positions, &todos, &view);
+            positions.iter().map(|&pos| todos[view[pos]].description.content.as_str()).collect::<Vec<_>>()
+        };
+        assert_eq!(sorted(ColumnSort::View), ["e", "d", "c", "b", "a"]);
+        assert_eq!(sorted(ColumnSort::Due), ["d", "b", "a", "e", "c"]);
+        assert_eq!(sorted(ColumnSort::Priority), ["c", "d", "a", "e", "b"]);
+    }
+
+    #[test]
+    fn sd_sorts_by_due_both_ways() {
+        let lines = ["b due:2030-01-02", "a due:2030-01-01", "x done due:2029-01-01", "none", "c due:2030-01-03"];
+        let mut f = Fixture::new("sd-desc", &lines);
+        f.run("sd");
+        assert_eq!(f.shown(), ["a", "b", "c"]);
+        f.run("sd desc");
+        assert_eq!(f.shown(), ["c", "b", "a"]);
+        f.run("sd 2 desc");
+        assert_eq!(f.shown(), ["c", "b"]);
+    }
+
+    #[test]
+    fn add_applies_the_default_priority_only_when_missing() {
+        let mut f = Fixture::new("default-priority", &[]);
+        f.session.config.default_priority = Some('C');
+        f.run("add plain");
+        f.run("add (A) urgent");
+        f.run("add x 2024-01-01 finished");
+        let saved = load_all(&f.session.file);
+        let priorities: Vec<Option<char>> = saved.iter().map(|t| t.priority).collect();
+        assert_eq!(priorities, [Some('C'), Some('A'), None]);
+    }
+
+    #[test]
+    fn use_switches_to_another_file() {
+        let mut f = Fixture::new("use", &["a"]);
+        let work = f.session.file.with_file_name("work.txt");
+        fs::write(&work, "w1\nw2\n").unwrap();
+        f.run("deferred on");
+        f.run("add b");
+        let res = f.run(&format!("use {}", work.display()));
+        assert_eq!(res.message, Some(format!("Using {} (2 task(s))", work.display())));
+        assert_eq!(f.shown(), ["w1", "w2"]);
+        assert_eq!(f.session.file, work);
+        // 切り替える前の未保存の変更は元のファイルに書かれている
+        assert!(!f.session.dirty);
+        assert_eq!(load_all(&work.with_file_name("todo.txt")).len(), 2);
+        f.run("add w3");
+        assert_eq!(load_all(&work).len(), 2);
+        f.run("w");
+        assert_eq!(load_all(&work).len(), 3);
+    }
+
+    #[test]
+    fn touch_resets_the_creation_date() {
+        let mut f = Fixture::new("touch", &["2020-01-01 old", "new"]);
+        assert!(f.run("touch 1").error.is_none());
+        assert!(f.run("touch 2").error.is_none());
+        let today = Local::now().date_naive();
+        let saved = load_all(&f.session.file);
+        assert!(saved.iter().all(|t| t.creation_date == Some(today)));
+        assert_eq!(saved[0].description.content, "old");
+        assert_eq!(f.run("touch 9").error.as_deref(), Some("Invalid ID"));
+    }
+
+    #[test]
+    fn stale_view_gives_invalid_id() {
+        let mut f = Fixture::new("stale-view", &["a", "b", "c"]);
+        // 別の処理で一覧が短くなったのに `view` が古いまま
+        f.todos.truncate(1);
+        assert_eq!(f.view, [0, 1, 2]);
+        for input in ["done 3", "edit 2 x", "due-set 3 2030-01-01", "touch 2"] {
+            assert!(f.run(input).error.is_some_and(|e| e.starts_with("Invalid ID")), "{}", input);
+        }
+        let path = f.session.file.with_file_name("out.md");
+        assert!(f.run(&format!("export md {}", path.display())).error.is_none());
+    }
+
+    #[test]
+    fn smart_weighs_due_against_priority() {
+        let lines = [
+            format!("(A) far due:{}", day(20)),
+            format!("(C) near due:{}", day(2)),
+            "(B) undated".to_string(),
+            format!("late due:{}", day(-5)),
+            "plain".to_string(),
+            format!("x (A) done due:{}", day(0)),
+        ];
+        let mut f = Fixture::new("smart", &lines.iter().map(String::as_str).collect::<Vec<_>>());
+        f.run("smart");
+        assert_eq!(f.shown(), ["near", "far", "undated", "late", "plain"]);
+        (f.session.config.smart_due_weight, f.session.config.smart_priority_weight) = (10, 1);
+        f.run("smart 3");
+        assert_eq!(f.shown(), ["late", "near", "far"]);
+    }
+
+    #[test]
+    fn add_top_puts_the_task_first() {
+        let mut f = Fixture::new("add-top", &["a", "b"]);
+        assert!(f.run("add-top urgent").error.is_none());
+        f.run("add last");
+        let saved = load_all(&f.session.file);
+        let contents: Vec<&str> = saved.iter().map(|t| t.description.content.as_str()).collect();
+        assert_eq!(contents, ["urgent", "a", "b", "last"]);
+        assert_eq!(f.shown(), contents);
+    }
+
+    #[test]
+    fn auto_pri_maps_urgency_and_respects_existing_priorities() {
+        let lines = [
+            format!("late due:{}", day(-1)),
+            format!("soon due:{}", day(3)),
+            format!("week due:{}", day(7)),
+            format!("later due:{}", day(30)),
+            format!("(C) kept due:{}", day(1)),
+            "undated".to_string(),
+            format!("x done due:{}", day(1)),
+        ];
+        let mut f = Fixture::new("auto-pri", &lines.iter().map(String::as_str).collect::<Vec<_>>());
+        let priorities = |f: &Fixture| f.todos.iter().map(|t| t.priority).collect::<Vec<_>>();
+        assert_eq!(f.run("auto-pri").message.as_deref(), Some("Set priority on 4 task(s)"));
+        assert_eq!(priorities(&f), [Some('A'), Some('A'), Some('B'), Some('C'), Some('C'), None, None]);
+        assert_eq!(f.run("auto-pri force").message.as_deref(), Some("Set priority on 1 task(s)"));
+        assert_eq!(priorities(&f)[4], Some('A'));
+        assert_eq!(load_all(&f.session.file)[4].priority, Some('A'));
+    }
+
+    #[test]
+    fn snooze_moves_the_due_date_or_counts_from_today() {
+        let mut f = Fixture::new("snooze", &["a due:2030-01-30", "b"]);
+        assert_eq!(f.run("snooze 1 3d").message.as_deref(), Some("Snoozed until 2030-02-02"));
+        assert_eq!(f.run("snooze 1 1m").message.as_deref(), Some("Snoozed until 2030-03-02"));
+        f.run("snooze 2 1w");
+        assert_eq!(f.todos[1].description.due, Some(Local::now().date_naive() + chrono::Duration::days(7)));
+        let saved = load_all(&f.session.file);
+        assert_eq!(saved[0].format(), "a due:2030-03-02");
+        assert_eq!(saved[1].description.due, f.todos[1].description.due);
+        assert!(matches!(Command::parse("snooze 1 soon"), Command::Unknown(_)));
+    }
+
+    #[test]
+    fn view_referring_to_a_view_is_refused() {
+        let mut f = Fixture::new("view-cycle", &["a", "b"]);
+        f.run("filter +x");
+        // `todo.views` を手で編集して互いに呼び合うようにした場合
+        fs::write(&f.session.views_file, "a\tview b\nb\tview a\nc\tlist\n").unwrap();
+        assert_eq!(f.run("view a").error.as_deref(), Some("View 'a' refers to another view"));
+        assert!(f.run("view c").error.is_none());
+        assert_eq!(f.shown(), ["a", "b"]);
+        // ビューを開いた直後に保存しても中身のコマンドが保存される
+        f.run("save d");
+        assert_eq!(load_views(&f.session.views_file)["d"], "list");
+    }
+
+    #[test]
+    fn filter_reads_quoted_tags() {
+        assert_eq!(split_filter_args(r#"+"Big Project"  -@"at home" +x"#), ["+Big Project", "-@at home", "+x"]);
+        assert_eq!(split_filter_args(r#"+"open"#), [r#"+"open"#]);
+        let mut f = Fixture::new("filter-quoted", &[r#"a +"Big Project""#, "b +Big", "c"]);
+        assert!(f.run(r#"filter +"big project""#).error.is_none());
+        assert_eq!(f.shown(), ["a"]);
+        f.run(r#"filter -+"Big Project""#);
+        assert_eq!(f.shown(), ["b", "c"]);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::theme::Theme;
+use chrono::format::{Item, StrftimeItems};
+use std::fs::read_to_string;
+
+/// 設定ファイル
+const CONFIG_FILE: &str = "todo.conf";
+/// `default_priority` を上書きする環境変数
+const DEFAULT_PRIORITY_ENV: &str = "TODO_DEFAULT_PRIORITY";
+
+/// todo 同士の区切り
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Separator {
+    /// 各 todo の上下に `-` の線
+    Line,
+    /// 各 todo の後に空行
+    Blank,
+    None,
+}
+
+/// アプリの設定
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 画面表示用の日付書式 (保存形式は常に `%Y-%m-%d`)
+    pub date_format: String,
+    pub theme: Theme,
+    /// 変更をすぐに書き込まず `w` で保存する
+    pub deferred_save: bool,
+    /// 起動時にこの行数を超えていたら警告する (0 なら警告しない)
+    pub max_lines: usize,
+    /// `filter` / `search` / `stats` で大文字・小文字を区別しない
+    pub fold_case: bool,
+    /// 重複の判定で優先度と期日の違いを無視する
+    pub loose_duplicates: bool,
+    /// 各 todo を 1 行にまとめて表示する
+    pub compact: bool,
+    /// 期日あり / なしの列の代わりに `+project` ごとにまとめて表示する
+    pub by_project: bool,
+    /// 完了済みの todo を各列の末尾に寄せる
+    pub completed_last: bool,
+    /// `add` で優先度を書かなかったときに付ける優先度
+    pub default_priority: Option<char>,
+    /// 期日に曜日を添えて表示する
+    pub show_weekday: bool,
+    /// 期日なしの列を左に置く
+    pub nodue_left: bool,
+    /// `smart` の点数で期日までの 1 日と優先度 1 段に掛ける重み
+    pub smart_due_weight: i64,
+    pub smart_priority_weight: i64,
+    pub separator: Separator,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            date_format: "%Y-%m-%d".into(), theme: Theme::default(), deferred_save: false, max_lines: 1000, fold_case: true, loose_duplicates: true,
+            compact: false, by_project: false, completed_last: false, default_priority: None, show_weekday: false, nodue_left: false,
+            smart_due_weight: 1, smart_priority_weight: 3, separator: Separator::Line,
+        }
+    }
+}
+
+impl Config {
+    /// `todo.conf` から読み込み (ファイルや項目が無ければ既定値．`default_priority` は環境変数を優先)
+    pub fn load() -> Self {
+        let mut config = Self::default();
+        if let Ok(txt) = read_to_string(CONFIG_FILE) {
+            for line in txt.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some((key, value)) = line.split_once('=') {
+                    config.set(key.trim(), value.trim());
+                }
+            }
+        }
+        if let Ok(value) = std::env::var(DEFAULT_PRIORITY_ENV) {
+            config.set("default_priority", value.trim());
+        }
+        config
+    }
+
+    /// `key = value` 1 行分を反映 (未知のキーや不正な値は無視)
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "date_format" if is_valid_date_format(value) => self.date_format = value.to_string(),
+            "compact" => self.compact = value == "true",
+            "by_project" => self.by_project = value == "true",
+            "completed_last" => self.completed_last = value == "true",
+            "columns_order" => match value {
+                "due,nodue" => self.nodue_left = false,
+                "nodue,due" => self.nodue_left = true,
+                _ => {}
+            },
+            "smart_due_weight" => self.smart_due_weight = value.parse().unwrap_or(self.smart_due_weight),
+            "smart_priority_weight" => self.smart_priority_weight = value.parse().unwrap_or(self.smart_priority_weight),
+            "separator" => match value {
+                "line" => self.separator = Separator::Line,
+                "blank" => self.separator = Separator::Blank,
+                "none" => self.separator = Separator::None,
+                _ => {}
+            },
+            "show_weekday" => self.show_weekday = value == "true",
+            "fold_case" => self.fold_case = value != "false",
+            "loose_duplicates" => self.loose_duplicates = value != "false",
+            "max_lines" => self.max_lines = value.parse().unwrap_or(self.max_lines),
+            "deferred_save" => self.deferred_save = value == "true",
+            "default_priority" => match value {
+                "none" => self.default_priority = None,
+                p if p.len() == 1 && p.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                    self.default_priority = p.chars().next().map(|c| c.to_ascii_uppercase());
+                }
+                _ => {}
+            },
+            "theme" => self.theme = Theme::by_name(value).unwrap_or(self.theme),
+            _ => {}
+        }
+    }
+}
+
+/// chrono が解釈できる書式か
+fn is_valid_date_format(fmt: &str) -> bool {
+    !StrftimeItems::new(fmt).any(|item| item == Item::Error)
+}
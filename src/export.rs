@@ -0,0 +1,153 @@
+use crate::todo::Todo;
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+
+/// エクスポート形式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "md" | "markdown" => Some(Self::Markdown),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    pub fn render(self, todos: &[&Todo]) -> String {
+        match self {
+            Self::Markdown => to_markdown(todos),
+            Self::Json => to_json(todos),
+        }
+    }
+}
+
+/// プロジェクトごとに見出しを付けたチェックボックスのリスト
+/// (複数のプロジェクトを持つタスクはそれぞれに載せる)
+pub fn to_markdown(todos: &[&Todo]) -> String {
+    let mut groups: BTreeMap<&str, Vec<&Todo>> = BTreeMap::new();
+    let mut no_project = Vec::new();
+    for &t in todos {
+        if t.description.projects.is_empty() {
+            no_project.push(t);
+        }
+        for p in &t.description.projects {
+            groups.entry(p).or_default().push(t);
+        }
+    }
+    let mut out = String::new();
+    let sections = groups
+        .iter()
+        .map(|(p, ts)| (format!("+{}", p), ts))
+        .chain((!no_project.is_empty()).then(|| ("No project".to_string(), &no_project)));
+    for (title, ts) in sections {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("## {}\n\n", title));
+        for t in ts {
+            out.push_str(&markdown_item(t));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+fn markdown_item(t: &Todo) -> String {
+    let mut parts = vec![if t.completion { "- [x]" } else { "- [ ]" }.to_string()];
+    if let Some(p) = t.priority { parts.push(format!("({})", p)); }
+    parts.push(t.description.content.clone());
+    parts.extend(t.description.contexts.iter().map(|c| format!("@{}", c)));
+    if let Some(d) = t.description.due_string() { parts.push(format!("due:{}", d)); }
+    parts.join(" ")
+}
+
+/// `Todo` の各フィールドをそのまま持つオブジェクトの配列
+pub fn to_json(todos: &[&Todo]) -> String {
+    let items: Vec<String> = todos.iter().map(|t| json_todo(t)).collect();
+    if items.is_empty() {
+        "[]\n".into()
+    } else {
+        format!("[\n{}\n]\n", items.join(",\n"))
+    }
+}
+
+fn json_todo(t: &Todo) -> String {
+    let d = &t.description;
+    let date = |date: Option<NaiveDate>| date.map_or("null".into(), |d| json_string(&d.format("%Y-%m-%d").to_string()));
+    let list = |v: &[String]| format!("[{}]", v.iter().map(|s| json_string(s)).collect::<Vec<_>>().join(", "));
+    format!(
+        concat!(
+            "  {{\"completion\": {}, \"priority\": {}, \"completion_date\": {}, \"creation_date\": {}, ",
+            "\"description\": {{\"content\": {}, \"projects\": {}, \"contexts\": {}, \"supplement\": {}, \"due\": {}, \"due_time\": {}, \"hidden\": {}}}, \"annotations\": {}}}",
+        ),
+        t.completion,
+        t.priority.map_or("null".into(), |p| json_string(&p.to_string())),
+        date(t.completion_date),
+        date(t.creation_date),
+        json_string(&d.content),
+        list(&d.projects),
+        list(&d.contexts),
+        list(&d.supplement),
+        date(d.due),
+        d.due_time.map_or("null".into(), |t| json_string(&t.format("%H:%M").to_string())),
+        d.hidden,
+        list(&t.annotations),
+    )
+}
+
+/// JSON の文字列リテラル
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fixture() -> Vec<Todo> {
+        ["(A) write +docs @desk due:2030-01-02", "x tidy up", "fix +code +docs"].iter().map(|l| Todo::parse(l)).collect()
+    }
+
+    #[test]
+    fn markdown_groups_by_project() {
+        let todos = fixture();
+        let refs: Vec<&Todo> = todos.iter().collect();
+        let expected = concat!(
+            "## +code\n\n- [ ] fix\n\n",
+            "## +docs\n\n- [ ] (A) write @desk due:2030-01-02\n- [ ] fix\n\n",
+            "## No project\n\n- [x] tidy up\n",
+        );
+        assert_eq!(to_markdown(&refs), expected);
+    }
+
+    #[test]
+    fn json_escapes_and_reimports() {
+        assert_eq!(to_json(&[]), "[]\n");
+        assert_eq!(json_string("a \"b\"\\\n\u{1}"), r#""a \"b\"\\\n\u0001""#);
+        let todos = fixture();
+        let refs: Vec<&Todo> = todos.iter().collect();
+        let json = to_json(&refs);
+        assert!(json.starts_with("[\n  {\"completion\": false, \"priority\": \"A\","));
+        let back = crate::import::parse_tasks(Path::new("export.json"), &json).unwrap();
+        let bare = |ts: &[Todo]| ts.iter().map(|t| Todo { raw: None, ..t.clone() }).collect::<Vec<_>>();
+        assert_eq!(bare(&back), bare(&todos));
+    }
+}
@@ -0,0 +1,267 @@
+use crate::storage::parse_lines;
+use crate::todo::{Description, Todo};
+use chrono::{NaiveDate, NaiveTime};
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+/// JSON の値
+enum Json {
+    Null,
+    Bool(bool),
+    Number,
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// 配列・オブジェクトの入れ子の上限 (深い入れ子でスタックを使い切らないように)
+const MAX_DEPTH: usize = 64;
+
+/// 再帰下降の JSON パーサ
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    /// 現在の入れ子の深さ
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_ws();
+        match self.chars.next() {
+            Some(got) if got == c => Ok(()),
+            Some(got) => Err(format!("expected '{}' but found '{}'", c, got)),
+            None => Err(format!("expected '{}' but reached the end", c)),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for c in word.chars() {
+            if self.chars.next() != Some(c) {
+                return Err(format!("invalid literal (expected '{}')", word));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_ws();
+        match self.chars.peek() {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                while self.chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)).is_some() {}
+                Ok(Json::Number)
+            }
+            Some(c) => Err(format!("unexpected '{}'", c)),
+            None => Err("unexpected end of input".into()),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.chars.next().ok_or("unterminated string")? {
+                '"' => return Ok(out),
+                '\\' => match self.chars.next().ok_or("unterminated string")? {
+                    'n' => out.push('\n'),
+                    't' => out.push('\t'),
+                    'r' => out.push('\r'),
+                    'b' => out.push('\u{8}'),
+                    'f' => out.push('\u{c}'),
+                    'u' => {
+                        let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        out.push(c.ok_or_else(|| format!("invalid escape \\u{}", hex))?);
+                    }
+                    c => out.push(c),
+                },
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// `open` と `close` で囲まれた `,` 区切りの並び
+    fn list<T>(&mut self, open: char, close: char, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        self.expect(open)?;
+        if self.depth == MAX_DEPTH {
+            return Err(format!("nested deeper than {} levels", MAX_DEPTH));
+        }
+        self.depth += 1;
+        let mut items = Vec::new();
+        self.skip_ws();
+        if self.chars.next_if_eq(&close).is_none() {
+            loop {
+                items.push(item(self)?);
+                self.skip_ws();
+                match self.chars.next() {
+                    Some(',') => {}
+                    Some(c) if c == close => break,
+                    _ => return Err(format!("expected ',' or '{}'", close)),
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(items)
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.list('[', ']', Self::value).map(Json::Array)
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.list('{', '}', |p| {
+            p.skip_ws();
+            let key = p.string()?;
+            p.expect(':')?;
+            Ok((key, p.value()?))
+        })
+        .map(Json::Object)
+    }
+}
+
+/// `export json` の形式 (`Todo` のフィールドを持つオブジェクトの配列) を読む
+fn parse_json(text: &str) -> Result<Vec<Todo>, String> {
+    let mut parser = Parser { chars: text.chars().peekable(), depth: 0 };
+    let value = parser.value()?;
+    parser.skip_ws();
+    if parser.chars.next().is_some() {
+        return Err("trailing characters after JSON".into());
+    }
+    let Json::Array(items) = value else { return Err("expected an array of tasks".into()) };
+    items.iter().enumerate().map(|(i, item)| json_todo(item).map_err(|e| format!("task {}: {}", i + 1, e))).collect()
+}
+
+fn json_todo(item: &Json) -> Result<Todo, String> {
+    // 改行などが入ると todo.txt の 1 行に収まらないので制御文字は受け付けない
+    let string = |v: Option<&Json>, key: &str| -> Result<Option<String>, String> {
+        match v {
+            None | Some(Json::Null) => Ok(None),
+            Some(Json::String(s)) if s.contains(char::is_control) => Err(format!("'{}' must not contain control characters", key)),
+            Some(Json::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(format!("'{}' must be a string", key)),
+        }
+    };
+    let date = |v: Option<&Json>, key: &str| -> Result<Option<NaiveDate>, String> {
+        string(v, key)?
+            .map(|s| NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(|_| format!("invalid date in '{}': {}", key, s)))
+            .transpose()
+    };
+    let list = |v: Option<&Json>, key: &str| -> Result<Vec<String>, String> {
+        match v {
+            None | Some(Json::Null) => Ok(Vec::new()),
+            Some(Json::Array(items)) => items.iter().map(|i| string(Some(i), key)?.ok_or(format!("'{}' must contain strings", key))).collect(),
+            Some(_) => Err(format!("'{}' must be an array", key)),
+        }
+    };
+    if !matches!(item, Json::Object(_)) {
+        return Err("expected an object".into());
+    }
+    let d = item.get("description").ok_or("missing 'description'")?;
+    let content = string(d.get("content"), "content")?.ok_or("missing 'content'")?;
+    let completion = match item.get("completion") {
+        None | Some(Json::Null) => false,
+        Some(Json::Bool(b)) => *b,
+        Some(_) => return Err("'completion' must be a boolean".into()),
+    };
+    let hidden = match d.get("hidden") {
+        None | Some(Json::Null) => false,
+        Some(Json::Bool(b)) => *b,
+        Some(_) => return Err("'hidden' must be a boolean".into()),
+    };
+    let annotations = list(item.get("annotations"), "annotations")?;
+    if annotations.iter().any(|a| !a.starts_with(char::is_whitespace)) {
+        return Err("'annotations' must start with whitespace".into());
+    }
+    let priority = match string(item.get("priority"), "priority")? {
+        None => None,
+        Some(p) if p.len() == 1 && p.starts_with(|c: char| c.is_ascii_alphabetic()) => p.chars().next().map(|c| c.to_ascii_uppercase()),
+        Some(p) => return Err(format!("invalid priority: {}", p)),
+    };
+    Ok(Todo {
+        completion,
+        priority,
+        completion_date: date(item.get("completion_date"), "completion_date")?,
+        creation_date: date(item.get("creation_date"), "creation_date")?,
+        description: Description {
+            content,
+            projects: list(d.get("projects"), "projects")?,
+            contexts: list(d.get("contexts"), "contexts")?,
+            supplement: list(d.get("supplement"), "supplement")?,
+            due: date(d.get("due"), "due")?,
+            due_time: string(d.get("due_time"), "due_time")?
+                .map(|s| NaiveTime::parse_from_str(&s, "%H:%M").map_err(|_| format!("invalid time in 'due_time': {}", s)))
+                .transpose()?,
+            hidden,
+        },
+        raw: None,
+        annotations,
+    })
+}
+
+/// JSON 配列または todo.txt 形式のテキストからタスクを読む
+/// (拡張子が `.json` なら JSON として読む．それ以外は先頭が `[` なら JSON を試し，読めなければ todo.txt として読む)
+pub fn parse_tasks(path: &Path, text: &str) -> Result<Vec<Todo>, String> {
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+        return parse_json(text);
+    }
+    if text.trim_start().starts_with('[') {
+        parse_json(text).or_else(|_| Ok(parse_lines(text)))
+    } else {
+        Ok(parse_lines(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let err = parse_json(&"[".repeat(200_000)).unwrap_err();
+        assert!(err.contains("nested deeper"), "{}", err);
+    }
+
+    #[test]
+    fn newline_in_content_is_rejected() {
+        let err = parse_json(r#"[{"description":{"content":"line1\n  line2"}}]"#).unwrap_err();
+        assert!(err.contains("control characters"), "{}", err);
+    }
+
+    #[test]
+    fn control_characters_in_tags_and_annotations_are_rejected() {
+        assert!(parse_json(r#"[{"description":{"content":"a","projects":["x\ty"]}}]"#).is_err());
+        assert!(parse_json(r#"[{"description":{"content":"a"},"annotations":["  a\nb"]}]"#).is_err());
+        assert!(parse_json(r#"[{"description":{"content":"a"},"annotations":["not indented"]}]"#).is_err());
+    }
+
+    #[test]
+    fn bracketed_todo_txt_falls_back_to_lines() {
+        let todos = parse_tasks(Path::new("imp.txt"), "[work] fix bug\nsecond\n").unwrap();
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].description.content, "[work] fix bug");
+    }
+
+    #[test]
+    fn json_extension_reports_json_errors() {
+        assert!(parse_tasks(Path::new("imp.json"), "[work] fix bug").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use ratatui::crossterm::event::KeyCode;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+
+/// キー割り当てファイル
+const KEYMAP_FILE: &str = "todo.keys";
+
+/// キーで呼び出す操作 (入力中のキーは対象外)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Quit,
+    Edit,
+    Add,
+    Search,
+    FocusUp,
+    FocusDown,
+    FocusNext,
+    FocusPrev,
+    Select,
+    Back,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Copy,
+    Toggle,
+    RaisePriority,
+    LowerPriority,
+}
+
+impl Action {
+    /// 通常モードで使う操作
+    pub const NORMAL: &[Action] = &[
+        Action::Quit, Action::Edit, Action::Add, Action::Search, Action::FocusUp, Action::FocusDown,
+        Action::FocusNext, Action::FocusPrev, Action::Select,
+    ];
+    /// 一覧を選択中に使う操作
+    pub const FOCUSED: &[Action] = &[
+        Action::Edit, Action::FocusUp, Action::FocusDown, Action::PageUp, Action::PageDown,
+        Action::Top, Action::Bottom, Action::Copy, Action::Toggle, Action::Select,
+        Action::RaisePriority, Action::LowerPriority, Action::Back,
+    ];
+
+    const ALL: &[(&str, Action)] = &[
+        ("quit", Action::Quit), ("edit", Action::Edit), ("add", Action::Add), ("search", Action::Search),
+        ("focus-up", Action::FocusUp), ("focus-down", Action::FocusDown), ("focus-next", Action::FocusNext),
+        ("focus-prev", Action::FocusPrev), ("select", Action::Select), ("back", Action::Back),
+        ("page-up", Action::PageUp), ("page-down", Action::PageDown), ("top", Action::Top), ("bottom", Action::Bottom),
+        ("copy", Action::Copy), ("toggle", Action::Toggle),
+        ("priority-up", Action::RaisePriority), ("priority-down", Action::LowerPriority),
+    ];
+
+    fn parse(s: &str) -> Option<Self> {
+        Self::ALL.iter().find(|(name, _)| *name == s).map(|&(_, a)| a)
+    }
+}
+
+/// 名前付きのキー (`Up`，`Enter` など)
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Up", KeyCode::Up), ("Down", KeyCode::Down), ("Left", KeyCode::Left), ("Right", KeyCode::Right),
+    ("Enter", KeyCode::Enter), ("Esc", KeyCode::Esc), ("Tab", KeyCode::Tab), ("BackTab", KeyCode::BackTab),
+    ("PageUp", KeyCode::PageUp), ("PageDown", KeyCode::PageDown), ("Home", KeyCode::Home), ("End", KeyCode::End),
+    ("Space", KeyCode::Char(' ')),
+];
+
+/// `Up` などの名前か 1 文字をキーとして解釈
+fn parse_key(s: &str) -> Option<KeyCode> {
+    if let Some(&(_, code)) = KEY_NAMES.iter().find(|(name, _)| *name == s) {
+        return Some(code);
+    }
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(KeyCode::Char(c)),
+        _ => None,
+    }
+}
+
+/// ヘッダーの案内に出すキーの表記
+fn key_label(code: KeyCode) -> String {
+    match code {
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Up => "↑".into(),
+        KeyCode::Down => "↓".into(),
+        code => KEY_NAMES.iter().find(|&&(_, k)| k == code).map_or("?".into(), |(name, _)| name.to_string()),
+    }
+}
+
+/// 操作ごとのキー割り当て
+pub struct Keymap {
+    bindings: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use KeyCode::*;
+        let bindings = [
+            (Action::Quit, vec![Char('q')]),
+            (Action::Edit, vec![Char('e')]),
+            (Action::Add, vec![Char('a')]),
+            (Action::Search, vec![Char('/')]),
+            (Action::FocusUp, vec![Up, Char('k')]),
+            (Action::FocusDown, vec![Down, Char('j')]),
+            (Action::FocusNext, vec![Tab]),
+            (Action::FocusPrev, vec![BackTab]),
+            (Action::Select, vec![Enter]),
+            (Action::Back, vec![Esc]),
+            (Action::PageUp, vec![PageUp]),
+            (Action::PageDown, vec![PageDown]),
+            (Action::Top, vec![Char('g')]),
+            (Action::Bottom, vec![Char('G')]),
+            (Action::Copy, vec![Char('y')]),
+            (Action::Toggle, vec![Char(' ')]),
+            (Action::RaisePriority, vec![Char('+')]),
+            (Action::LowerPriority, vec![Char('-')]),
+        ];
+        Self { bindings: bindings.into_iter().collect() }
+    }
+}
+
+impl Keymap {
+    /// `todo.keys` から読み込み (`操作 = キー, キー` の行．書かれた操作だけ既定の割り当てを置き換える)
+    pub fn load() -> Self {
+        let mut keymap = Self::default();
+        if let Ok(txt) = read_to_string(KEYMAP_FILE) {
+            for line in txt.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                if let Some((action, keys)) = line.split_once('=') {
+                    keymap.set(action.trim(), keys);
+                }
+            }
+        }
+        keymap
+    }
+
+    /// 1 行分を反映 (未知の操作や解釈できないキーがあれば無視)
+    fn set(&mut self, action: &str, keys: &str) {
+        let Some(action) = Action::parse(action) else { return };
+        let keys: Option<Vec<KeyCode>> = keys.split(',').map(|k| parse_key(k.trim())).collect();
+        if let Some(keys) = keys.filter(|k| !k.is_empty()) {
+            self.bindings.insert(action, keys);
+        }
+    }
+
+    /// `candidates` のうち `code` が割り当てられている操作
+    pub fn action(&self, code: KeyCode, candidates: &[Action]) -> Option<Action> {
+        candidates.iter().copied().find(|a| self.bindings.get(a).is_some_and(|keys| keys.contains(&code)))
+    }
+
+    /// 操作に割り当てられた最初のキーの表記
+    pub fn label(&self, action: Action) -> String {
+        self.bindings.get(&action).and_then(|keys| keys.first()).map_or(String::new(), |&k| key_label(k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remapped_quit_replaces_the_default_key() {
+        let mut keymap = Keymap::default();
+        assert_eq!(keymap.action(KeyCode::Char('q'), Action::NORMAL), Some(Action::Quit));
+        keymap.set("quit", "x, Esc");
+        assert_eq!(keymap.action(KeyCode::Char('x'), Action::NORMAL), Some(Action::Quit));
+        assert_eq!(keymap.action(KeyCode::Esc, &[Action::Quit]), Some(Action::Quit));
+        assert_eq!(keymap.action(KeyCode::Char('q'), Action::NORMAL), None);
+        assert_eq!(keymap.label(Action::Quit), "x");
+        // 解釈できないキーがあれば割り当ては変えない
+        keymap.set("quit", "x, Ctrl-Q");
+        keymap.set("no-such-action", "z");
+        assert_eq!(keymap.label(Action::Quit), "x");
+        assert_eq!(keymap.action(KeyCode::Char('z'), Action::NORMAL), None);
+    }
+}
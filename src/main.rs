@@ -1,17 +1,119 @@
 mod app;
+mod clipboard;
 mod command;
+mod config;
+mod export;
+mod import;
+mod keymap;
+mod stats;
 mod storage;
+mod theme;
 mod todo;
 
 use app::App;
 use color_eyre::Result;
+use ratatui::crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use ratatui::crossterm::execute;
+use command::{default_view, execute_command, Command, Session};
+use config::Config;
+use std::path::Path;
+use storage::{create_if_missing, load_all, load_from, TODO_FILE};
+use todo::Todo;
 use ratatui::init as tui_init;
 use ratatui::restore as tui_restore;
 
+/// TUI なしで実行するときのセッション
+/// (1 回きりの実行なので保存を遅らせる設定は使わない．`from_stdin` なら標準入力の一覧を書き込み禁止で扱う)
+fn headless_session(read_only: bool, from_stdin: bool) -> Session {
+    let mut session = Session::new(Config { deferred_save: false, ..Config::load() });
+    session.read_only = read_only || from_stdin;
+    session.from_stdin = from_stdin;
+    session
+}
+
+/// 引数で渡されたコマンドを TUI なしで実行 (表示系のコマンドなら結果の一覧を出力)
+fn run_headless(args: &[String], mut todos: Vec<Todo>, mut session: Session) -> Result<()> {
+    let mut view = default_view(&todos, &session);
+    let input = args.join(" ");
+    let res = execute_command(&mut todos, &mut view, &mut session, &input);
+    if let Some(err) = res.error {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    if Command::parse(&input).is_view() {
+        for t in view.iter().filter_map(|&i| todos.get(i)) {
+            println!("{}", t.to_line());
+        }
+    }
+    if let Some(msg) = res.message {
+        println!("{}", msg);
+    }
+    Ok(())
+}
+
+/// パニックしたら `restore` で端末を元に戻してから元のフックでメッセージを出す
+fn install_panic_hook(restore: impl Fn() + Send + Sync + 'static) {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore();
+        hook(info);
+    }));
+}
+
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args == ["--init"] {
+        let created = create_if_missing()?;
+        println!("{}", if created { "Created todo.txt" } else { "todo.txt already exists" });
+        return Ok(());
+    }
+    // `--stdin` なら標準入力の一覧を書き込み禁止で扱う
+    let app = match args.split_first() {
+        Some((first, rest)) if first == "--stdin" => {
+            let todos = load_from(std::io::stdin())?;
+            if !rest.is_empty() {
+                return run_headless(rest, todos, headless_session(true, true));
+            }
+            App::read_only(todos, "Read-only: showing tasks from standard input", true)
+        }
+        // `--readonly` なら todo.txt を書き込み禁止で扱う
+        Some((first, rest)) if first == "--readonly" => {
+            if !rest.is_empty() {
+                return run_headless(rest, load_all(Path::new(TODO_FILE)), headless_session(true, false));
+            }
+            App::read_only(load_all(Path::new(TODO_FILE)), "Read-only mode: commands that change the list are disabled", false)
+        }
+        Some(_) => return run_headless(&args, load_all(Path::new(TODO_FILE)), headless_session(false, false)),
+        None => App::new(),
+    };
     let terminal = tui_init();
-    let res = App::new().run(terminal);
+    execute!(std::io::stdout(), EnableMouseCapture)?;
+    install_panic_hook(|| {
+        let _ = execute!(std::io::stdout(), DisableMouseCapture);
+        tui_restore();
+    });
+    let res = app.run(terminal);
+    let released = execute!(std::io::stdout(), DisableMouseCapture);
     tui_restore();
-    res
-}
\ No newline at end of file
+    res?;
+    Ok(released?)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn panic_hook_runs_restore() {
+        static RESTORED: AtomicUsize = AtomicUsize::new(0);
+        install_panic_hook(|| {
+            RESTORED.fetch_add(1, Ordering::SeqCst);
+        });
+        let res = std::panic::catch_unwind(|| panic!("expected panic from panic_hook_runs_restore"));
+        // 既定のフックに戻す
+        let _ = std::panic::take_hook();
+        assert!(res.is_err());
+        assert!(RESTORED.load(Ordering::SeqCst) >= 1);
+    }
+}
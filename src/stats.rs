@@ -0,0 +1,106 @@
+use crate::todo::{normalize, Todo};
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// 一覧の集計結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub total: usize,
+    pub completed: usize,
+    pub incomplete: usize,
+    pub overdue: usize,
+    /// 未完了タスクの優先度ごとの件数
+    pub priorities: BTreeMap<char, usize>,
+    pub projects: usize,
+    pub contexts: usize,
+}
+
+impl Stats {
+    /// `todos` 全体を集計 (`fold_case` ならプロジェクト・コンテキストの大文字・小文字を区別しない)
+    pub fn compute(todos: &[Todo], today: NaiveDate, fold_case: bool) -> Self {
+        let completed = todos.iter().filter(|t| t.completion).count();
+        let overdue = todos.iter().filter_map(Todo::due_uncompleted).filter(|&d| d < today).count();
+        let mut priorities = BTreeMap::new();
+        for p in todos.iter().filter_map(Todo::priority_uncompleted) {
+            *priorities.entry(p).or_insert(0) += 1;
+        }
+        let projects: BTreeSet<String> = todos.iter().flat_map(|t| &t.description.projects).map(|p| normalize(p, fold_case)).collect();
+        let contexts: BTreeSet<String> = todos.iter().flat_map(|t| &t.description.contexts).map(|c| normalize(c, fold_case)).collect();
+        Self {
+            total: todos.len(),
+            completed,
+            incomplete: todos.len() - completed,
+            overdue,
+            priorities,
+            projects: projects.len(),
+            contexts: contexts.len(),
+        }
+    }
+}
+
+/// タグごとの件数を名前順に返す
+/// (`fold_case` なら大文字・小文字を区別せず，最初に現れた表記でまとめる)
+pub fn tag_counts<'a>(todos: &'a [Todo], tags: impl Fn(&'a Todo) -> &'a [String], fold_case: bool) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<String, (&String, usize)> = BTreeMap::new();
+    for tag in todos.iter().flat_map(tags) {
+        counts.entry(normalize(tag, fold_case)).or_insert((tag, 0)).1 += 1;
+    }
+    counts.into_values().map(|(tag, n)| (tag.clone(), n)).collect()
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "total {} / done {} / open {} / overdue {}", self.total, self.completed, self.incomplete, self.overdue)?;
+        if !self.priorities.is_empty() {
+            let pri: Vec<String> = self.priorities.iter().map(|(p, n)| format!("({}) {}", p, n)).collect();
+            write!(f, " | {}", pri.join(" "))?;
+        }
+        write!(f, " | +{} @{}", self.projects, self.contexts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todos(lines: &[&str]) -> Vec<Todo> {
+        lines.iter().map(|l| Todo::parse(l)).collect()
+    }
+
+    #[test]
+    fn compute_counts_a_known_fixture() {
+        let today = NaiveDate::from_ymd_opt(2024, 6, 10).unwrap();
+        let list = todos(&[
+            "(A) a +work @office due:2024-06-01",
+            "(A) b +Work due:2024-06-20",
+            "(B) c @home",
+            "x (C) d +home due:2024-06-01",
+            "e",
+        ]);
+        let stats = Stats::compute(&list, today, true);
+        assert_eq!(
+            stats,
+            Stats {
+                total: 5,
+                completed: 1,
+                incomplete: 4,
+                overdue: 1,
+                priorities: BTreeMap::from([('A', 2), ('B', 1)]),
+                projects: 2,
+                contexts: 2,
+            }
+        );
+        assert_eq!(Stats::compute(&list, today, false).projects, 3);
+        assert_eq!(stats.to_string(), "total 5 / done 1 / open 4 / overdue 1 | (A) 2 (B) 1 | +2 @2");
+    }
+
+    #[test]
+    fn tag_counts_sorts_by_name_and_folds_case() {
+        let todos: Vec<Todo> = ["a +Work @home", "b +work +garden", "x c +garden"].iter().map(|l| Todo::parse(l)).collect();
+        let projects = |fold| tag_counts(&todos, |t| &t.description.projects, fold);
+        assert_eq!(projects(true), [("garden".to_string(), 2), ("Work".to_string(), 2)]);
+        assert_eq!(projects(false), [("Work".to_string(), 1), ("garden".to_string(), 2), ("work".to_string(), 1)]);
+        assert_eq!(tag_counts(&todos, |t| &t.description.contexts, true), [("home".to_string(), 1)]);
+    }
+}
@@ -1,34 +1,217 @@
 use crate::todo::Todo;
+use std::collections::BTreeMap;
 use std::fs::{read_to_string, OpenOptions};
-use std::io::{self, Write};
+use std::io::{self, Read, Write};
+use std::path::Path;
 
-/// `todo.txt` から全件ロード
-pub fn load_all() -> Vec<Todo> {
-    match read_to_string("todo.txt") {
-        Ok(txt) => txt.lines().map(Todo::parse).collect(),
+/// 既定の todo ファイル (`use` で切り替えられる)
+pub const TODO_FILE: &str = "todo.txt";
+/// 既定の保存済みビューのファイル
+pub const VIEWS_FILE: &str = "todo.views";
+
+/// `todo.txt` が無ければ空のファイルを作る (作ったら `true`)
+pub fn create_if_missing() -> io::Result<bool> {
+    match OpenOptions::new().write(true).create_new(true).open(TODO_FILE) {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// `path` から全件ロード (読めなければ空)
+pub fn load_all(path: &Path) -> Vec<Todo> {
+    match read_to_string(path) {
+        Ok(txt) => parse_lines(&txt),
         Err(_) => Vec::new(),
     }
 }
 
-/// `todo.txt` を上書き
-pub fn rewrite_file(todos: &[Todo]) -> io::Result<()> {
+/// `path` の行数 (注釈や空行も数える．読めなければ 0)
+pub fn line_count(path: &Path) -> usize {
+    read_to_string(path).map(|txt| txt.lines().count()).unwrap_or(0)
+}
+
+/// 任意の入力 (標準入力など) から全件ロード
+pub fn load_from(mut reader: impl Read) -> io::Result<Vec<Todo>> {
+    let mut txt = String::new();
+    reader.read_to_string(&mut txt)?;
+    Ok(parse_lines(&txt))
+}
+
+/// 行ごとにパース (空行は読み飛ばし，空白で始まる行は直前のタスクの注釈としてまとめる)
+pub fn parse_lines(txt: &str) -> Vec<Todo> {
+    let mut todos: Vec<Todo> = Vec::new();
+    for line in txt.lines().filter(|l| !l.trim().is_empty()) {
+        match todos.last_mut() {
+            Some(t) if line.starts_with(char::is_whitespace) => {
+                t.annotations.push(line.to_string());
+            }
+            _ => todos.push(Todo::parse(line)),
+        }
+    }
+    todos
+}
+
+/// 書式化して読み直すと内容が変わるタスクの行番号 (1 始まり) を `parse_lines` と同じ規則で探す
+pub fn lossy_lines(txt: &str) -> Vec<usize> {
+    let mut lossy = Vec::new();
+    let mut seen_task = false;
+    for (n, line) in txt.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        if seen_task && line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        seen_task = true;
+        if !Todo::parse(line).round_trips() {
+            lossy.push(n + 1);
+        }
+    }
+    lossy
+}
+
+/// `path` を上書き (変更の無いタスクは元の行のまま)
+pub fn rewrite_file(path: &Path, todos: &[Todo]) -> io::Result<()> {
     let mut f = OpenOptions::new()
         .write(true)
         .truncate(true)
         .create(true)
-        .open("todo.txt")?;
+        .open(path)?;
     for t in todos {
-        writeln!(f, "{}", t.format())?;
+        writeln!(f, "{}", t.to_line())?;
+        for a in &t.annotations {
+            writeln!(f, "{}", a)?;
+        }
     }
     Ok(())
 }
 
-/// `todo.txt` に追記
-pub fn append_one(todo: &Todo) -> io::Result<()> {
+/// `path` に追記
+pub fn append_one(path: &Path, todo: &Todo) -> io::Result<()> {
     let mut f = OpenOptions::new()
         .append(true)
         .create(true)
-        .open("todo.txt")?;
+        .open(path)?;
     writeln!(f, "{}", todo.format())?;
+    for a in &todo.annotations {
+        writeln!(f, "{}", a)?;
+    }
+    Ok(())
+}
+
+/// 変更履歴のファイルに行を追記
+pub fn append_log(path: &Path, lines: &[String]) -> io::Result<()> {
+    let mut f = OpenOptions::new().append(true).create(true).open(path)?;
+    for line in lines {
+        writeln!(f, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// 最後に振った `id:` を読む (ファイルが無ければ 0)
+pub fn load_id_mark(path: &Path) -> u64 {
+    read_to_string(path).ok().and_then(|s| s.trim().parse().ok()).unwrap_or(0)
+}
+
+/// 最後に振った `id:` を記録
+pub fn save_id_mark(path: &Path, id: u64) -> io::Result<()> {
+    std::fs::write(path, format!("{}\n", id))
+}
+
+/// `path` から保存済みビューを読み込み (`名前<TAB>コマンド` の行)
+pub fn load_views(path: &Path) -> BTreeMap<String, String> {
+    read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| l.split_once('\t'))
+        .map(|(name, cmd)| (name.to_string(), cmd.to_string()))
+        .collect()
+}
+
+/// `path` にビューを保存 (同名のものは上書き)
+pub fn save_view(path: &Path, name: &str, command: &str) -> io::Result<()> {
+    let mut views = load_views(path);
+    views.insert(name.to_string(), command.to_string());
+    let mut f = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(path)?;
+    for (name, cmd) in &views {
+        writeln!(f, "{}\t{}", name, cmd)?;
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テストごとの一時ディレクトリ
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("todo-storage-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn line_count_includes_annotations_and_blank_lines() {
+        let path = temp_dir("line-count").join("todo.txt");
+        std::fs::write(&path, "a\n  note\n\nb\n").unwrap();
+        assert_eq!(line_count(&path), 4);
+        assert_eq!(load_all(&path).len(), 2);
+    }
+
+    #[test]
+    fn saved_views_load_back() {
+        let path = temp_dir("views").join("todo.views");
+        assert!(load_views(&path).is_empty());
+        save_view(&path, "week", "due 2024-06-01 2024-06-07").unwrap();
+        save_view(&path, "top", "sp 5").unwrap();
+        save_view(&path, "week", "sd 3").unwrap();
+        let views = load_views(&path);
+        assert_eq!(views.len(), 2);
+        assert_eq!(views["week"], "sd 3");
+        assert_eq!(views["top"], "sp 5");
+    }
+
+    #[test]
+    fn annotations_survive_a_rewrite() {
+        let text = "a\n  first note\n\tsecond note\nb\n";
+        let mut todos = parse_lines(text);
+        assert_eq!(todos[0].annotations, ["  first note", "\tsecond note"]);
+        assert!(todos[1].annotations.is_empty());
+        let path = temp_dir("annotations").join("todo.txt");
+        rewrite_file(&path, &todos).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), text);
+        todos[0].mark_done();
+        rewrite_file(&path, &todos).unwrap();
+        assert_eq!(load_all(&path)[0].annotations, ["  first note", "\tsecond note"]);
+    }
+
+    #[test]
+    fn blank_lines_are_skipped() {
+        let todos = parse_lines("\n  \na\n\n\t\nb\n\n\n");
+        let contents: Vec<&str> = todos.iter().map(|t| t.description.content.as_str()).collect();
+        assert_eq!(contents, ["a", "b"]);
+        assert!(todos.iter().all(|t| t.annotations.is_empty()));
+    }
+
+    #[test]
+    fn load_from_reads_any_reader() {
+        let todos = load_from(io::Cursor::new("(A) a +p\n  note\nx b\n")).unwrap();
+        assert_eq!(todos.len(), 2);
+        assert_eq!(todos[0].annotations, ["  note"]);
+        assert!(todos[1].completion);
+        assert!(load_from(io::Cursor::new([0xffu8, 0xfe])).is_err());
+    }
+
+    #[test]
+    fn lossy_lines_reports_lines_that_change_when_rewritten() {
+        // 本文が `+p"` より前に回ると `"q +p"` がひとつの語として読まれる
+        let lossy = "+p\" \"q";
+        assert!(!Todo::parse(lossy).round_trips());
+        let text = format!("a\n  {}\n\n{}\nb due:2024-1-2\n", lossy, lossy);
+        assert_eq!(lossy_lines(&text), [4]);
+        assert!(lossy_lines("(A) a +p @c due:2024-01-01\n\nx b\n").is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::todo::Urgency;
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Span;
+
+/// 画面の配色
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub name: &'static str,
+    /// フォーカス中のブロックの枠
+    pub focus: Style,
+    /// 編集中の入力欄の文字
+    pub editing: Style,
+    pub error: Style,
+    pub message: Style,
+    /// 空の列の案内文
+    pub hint: Style,
+    /// 期日の近さごとのマーカー (期日切れ / 3 日以内 / 7 日以内 / それ以降 / 期日なし)
+    pub markers: [(&'static str, Style); 5],
+    /// `+project` / `@context` を名前ごとに色分けするか
+    pub tag_colors: bool,
+}
+
+/// プロジェクトの色 (コンテキストとは別の系統)
+const PROJECT_COLORS: [Color; 6] = [Color::Blue, Color::Cyan, Color::Green, Color::LightBlue, Color::LightCyan, Color::LightGreen];
+/// コンテキストの色
+const CONTEXT_COLORS: [Color; 6] = [Color::Magenta, Color::Yellow, Color::LightMagenta, Color::LightYellow, Color::LightRed, Color::Red];
+
+/// タグ名から毎回同じ色を選ぶ (FNV-1a ハッシュ)
+fn tag_color(name: &str, palette: &[Color]) -> Color {
+    let hash = name.bytes().fold(0xcbf29ce484222325u64, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3));
+    palette[(hash % palette.len() as u64) as usize]
+}
+
+impl Theme {
+    pub const DEFAULT: Self = Self {
+        name: "default",
+        focus: Style::new().fg(Color::Yellow),
+        editing: Style::new().fg(Color::Yellow),
+        error: Style::new().fg(Color::Red),
+        message: Style::new().fg(Color::Cyan),
+        hint: Style::new().fg(Color::DarkGray),
+        markers: [
+            ("!", Style::new().fg(Color::White).bg(Color::Magenta).add_modifier(Modifier::SLOW_BLINK)),
+            (" ", Style::new().bg(Color::Red)),
+            (" ", Style::new().bg(Color::Yellow)),
+            (" ", Style::new().bg(Color::Green)),
+            (" ", Style::new().bg(Color::Gray)),
+        ],
+        tag_colors: true,
+    };
+
+    pub const HIGH_CONTRAST: Self = Self {
+        name: "high-contrast",
+        focus: Style::new().fg(Color::LightYellow).add_modifier(Modifier::BOLD),
+        editing: Style::new().fg(Color::White).add_modifier(Modifier::BOLD),
+        error: Style::new().fg(Color::White).bg(Color::Red),
+        message: Style::new().fg(Color::Black).bg(Color::Cyan),
+        hint: Style::new().fg(Color::White),
+        markers: [
+            ("!", Style::new().fg(Color::Black).bg(Color::LightMagenta).add_modifier(Modifier::BOLD)),
+            (" ", Style::new().bg(Color::LightRed)),
+            (" ", Style::new().bg(Color::LightYellow)),
+            (" ", Style::new().bg(Color::LightGreen)),
+            (" ", Style::new().bg(Color::White)),
+        ],
+        tag_colors: true,
+    };
+
+    /// 色を使わず記号と文字修飾だけで区別する
+    pub const MONOCHROME: Self = Self {
+        name: "monochrome",
+        focus: Style::new().add_modifier(Modifier::BOLD),
+        editing: Style::new().add_modifier(Modifier::BOLD),
+        error: Style::new().add_modifier(Modifier::REVERSED),
+        message: Style::new().add_modifier(Modifier::BOLD),
+        hint: Style::new().add_modifier(Modifier::DIM),
+        markers: [
+            ("!", Style::new().add_modifier(Modifier::REVERSED.union(Modifier::SLOW_BLINK))),
+            ("!", Style::new().add_modifier(Modifier::BOLD)),
+            ("*", Style::new()),
+            ("-", Style::new()),
+            (" ", Style::new()),
+        ],
+        tag_colors: false,
+    };
+
+    pub const ALL: [Self; 3] = [Self::DEFAULT, Self::HIGH_CONTRAST, Self::MONOCHROME];
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.name == name)
+    }
+
+    /// `+project` (`@context` なら `context = true`) の表示スタイル
+    pub fn tag(&self, name: &str, context: bool) -> Style {
+        match (self.tag_colors, context) {
+            (false, _) => Style::new(),
+            (true, false) => Style::new().fg(tag_color(name, &PROJECT_COLORS)),
+            (true, true) => Style::new().fg(tag_color(name, &CONTEXT_COLORS)),
+        }
+    }
+
+    /// 期日の近さに応じたマーカー
+    pub fn marker(&self, urgency: Urgency) -> Span<'static> {
+        let (symbol, style) = self.markers[urgency as usize];
+        Span::styled(symbol, style)
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::todo::Todo;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn overdue_marker_differs_from_soon() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        assert_eq!(Todo::parse("a due:2024-02-29").urgency(today), Urgency::Overdue);
+        assert_eq!(Todo::parse("a due:2024-03-02").urgency(today), Urgency::Soon);
+        for theme in Theme::ALL {
+            assert_ne!(theme.marker(Urgency::Overdue), theme.marker(Urgency::Soon), "{}", theme.name);
+        }
+    }
+
+    #[test]
+    fn tag_color_is_stable_per_name() {
+        assert_eq!(tag_color("work", &PROJECT_COLORS), tag_color("work", &PROJECT_COLORS));
+        assert_eq!(Theme::DEFAULT.tag("work", false), Theme::DEFAULT.tag("work", false));
+        assert!(PROJECT_COLORS.contains(&tag_color("work", &PROJECT_COLORS)));
+        assert!(CONTEXT_COLORS.contains(&Theme::DEFAULT.tag("work", true).fg.unwrap()));
+        // 名前が違えばどこかで色が分かれる
+        let names = ["work", "home", "garden", "errands", "phone", "email"];
+        assert!(names.iter().any(|n| tag_color(n, &PROJECT_COLORS) != tag_color("work", &PROJECT_COLORS)));
+        assert_eq!(Theme::MONOCHROME.tag("work", false), Style::new());
+    }
+}
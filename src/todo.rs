@@ -1,30 +1,244 @@
-use chrono::{Local, NaiveDate};
-use ratatui::style::Color;
+use chrono::{Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, NaiveTime};
+
+/// 期日の近さ (マーカー表示の区分)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Urgency {
+    /// 期日切れ (未完了のみ)
+    Overdue,
+    /// 3 日以内
+    Soon,
+    /// 7 日以内
+    Week,
+    Later,
+    NoDue,
+}
+
+impl Urgency {
+    /// `auto-pri` で付ける優先度 (期日切れ・3 日以内は A，7 日以内は B，それ以降は C)
+    pub fn auto_priority(self) -> Option<char> {
+        match self {
+            Urgency::Overdue | Urgency::Soon => Some('A'),
+            Urgency::Week => Some('B'),
+            Urgency::Later => Some('C'),
+            Urgency::NoDue => None,
+        }
+    }
+}
+
+/// `3d` / `2w` / `1m` / `1y` の形の期間 (日 / 週 / 月 / 年)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Offset {
+    n: u32,
+    unit: char,
+}
+
+impl Offset {
+    pub fn parse(s: &str) -> Option<Self> {
+        let unit = s.chars().last().filter(|u| matches!(u, 'd' | 'w' | 'm' | 'y'))?;
+        let n = s[..s.len() - 1].parse().ok()?;
+        Some(Self { n, unit })
+    }
+
+    /// `date` からこの期間だけ後の日 (月末を越える日は月末に丸める)
+    pub fn add_to(self, date: NaiveDate) -> Option<NaiveDate> {
+        match self.unit {
+            'd' => date.checked_add_days(Days::new(self.n.into())),
+            'w' => date.checked_add_days(Days::new(u64::from(self.n) * 7)),
+            'm' => date.checked_add_months(Months::new(self.n)),
+            _ => date.checked_add_months(Months::new(self.n.checked_mul(12)?)),
+        }
+    }
+}
+
+/// `when` で期日の近さごとにまとめる区分
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bucket {
+    Overdue,
+    Today,
+    /// 7 日以内
+    ThisWeek,
+    Later,
+}
+
+impl Bucket {
+    pub fn label(self) -> &'static str {
+        match self {
+            Bucket::Overdue => "Overdue",
+            Bucket::Today => "Today",
+            Bucket::ThisWeek => "This Week",
+            Bucket::Later => "Later",
+        }
+    }
+}
 
 /// 説明部分
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Description {
     pub content: String,
-    pub project: Option<String>,
-    pub context: Option<String>,
-    pub supplement: Option<String>,
+    pub projects: Vec<String>,
+    pub contexts: Vec<String>,
+    /// `key:value` タグ (due は `due` で持つので含まない)
+    pub supplement: Vec<String>,
     pub due: Option<NaiveDate>,
+    /// 期日の時刻 (`due:YYYY-MM-DDTHH:MM` のとき)
+    pub due_time: Option<NaiveTime>,
+    /// `h:1` で通常の一覧から隠す
+    pub hidden: bool,
+}
+
+impl Description {
+    /// `due:` の値 (時刻があれば `YYYY-MM-DDTHH:MM`)
+    pub fn due_string(&self) -> Option<String> {
+        let d = self.due?.format("%Y-%m-%d");
+        Some(match self.due_time {
+            Some(t) => format!("{}T{}", d, t.format("%H:%M")),
+            None => d.to_string(),
+        })
+    }
+
+    /// `key:value` タグの値を取得
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.supplement.iter().find_map(|kv| kv.split_once(':').filter(|(k, _)| *k == key).map(|(_, v)| v))
+    }
+
+    /// `key:value` タグを設定 (`None` なら削除)
+    pub fn set_tag(&mut self, key: &str, value: Option<&str>) {
+        let pos = self.supplement.iter().position(|kv| kv.split_once(':').is_some_and(|(k, _)| k == key));
+        match (pos, value) {
+            (Some(i), Some(v)) => self.supplement[i] = format!("{}:{}", key, v),
+            (Some(i), None) => { self.supplement.remove(i); }
+            (None, Some(v)) => self.supplement.push(format!("{}:{}", key, v)),
+            (None, None) => {}
+        }
+    }
 }
 
 /// Todo 本体
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Todo {
     pub completion: bool,
     pub priority: Option<char>,
     pub completion_date: Option<NaiveDate>,
     pub creation_date: Option<NaiveDate>,
     pub description: Description,
+    /// 読み込んだときの元の行
+    pub raw: Option<String>,
+    /// タスクの行に続くインデントされた行 (インデントも含めてそのまま保持)
+    pub annotations: Vec<String>,
+}
+
+/// 空白区切りでトークン化し `(開始バイト位置, トークン)` を返す
+/// (語の先頭か `+` / `@` / `key:` の直後の `"` から閉じる `"` までは空白を含めて 1 トークン．
+/// 閉じる `"` が無い `"` や語の途中の `"` はただの文字)
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        if quoted {
+            quoted = c != '"';
+            continue;
+        }
+        if c.is_whitespace() {
+            if let Some(s) = start.take() {
+                tokens.push((s, &line[s..i]));
+            }
+            continue;
+        }
+        let s = *start.get_or_insert(i);
+        if c == '"' && opens_quote(&line[s..i]) && line[i + 1..].contains('"') {
+            quoted = true;
+        }
+    }
+    if let Some(s) = start {
+        tokens.push((s, &line[s..]));
+    }
+    tokens
+}
+
+/// トークンの先頭からここまでが `"` で囲み始めてよい位置か (空，`+`，`@`，`key:`)
+fn opens_quote(prefix: &str) -> bool {
+    match prefix.strip_suffix(':') {
+        Some(key) => key.starts_with(|c: char| c.is_ascii_alphabetic()) && key.chars().all(|c| c.is_ascii_alphanumeric()),
+        None => matches!(prefix, "" | "+" | "@"),
+    }
+}
+
+/// 両端の `"` を外す
+fn unquote(s: &str) -> &str {
+    s.strip_prefix('"').and_then(|s| s.strip_suffix('"')).unwrap_or(s)
+}
+
+/// 空白を含む値を `"` で囲む
+pub fn quote(s: &str) -> String {
+    if s.contains(char::is_whitespace) { format!("\"{}\"", s) } else { s.to_string() }
+}
+
+/// `due:` の値をパース (`YYYY-MM-DD` または `YYYY-MM-DDTHH:MM`)
+pub fn parse_due(s: &str) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    parse_full_due(s).or_else(|| resolve_partial_date(s, Local::now().date_naive()).map(|d| (d, None)))
+}
+
+/// 年月日がそろった期日 (`YYYY-MM-DD` / `YYYY-MM-DDTHH:MM`)
+fn parse_full_due(s: &str) -> Option<(NaiveDate, Option<NaiveTime>)> {
+    if let Ok(dt) = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M") {
+        return Some((dt.date(), Some(dt.time())));
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().map(|d| (d, None))
+}
+
+/// 省略した期日を `today` を基準に日付にする
+/// (`YYYY-MM` はその月の末日，`MM-DD` は今日以降で最初のその日)
+pub fn resolve_partial_date(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let (a, b) = s.split_once('-')?;
+    let (a, b): (u32, u32) = match (a.len(), b.len()) {
+        (4, 2) | (2, 2) => (a.parse().ok()?, b.parse().ok()?),
+        _ => return None,
+    };
+    if s.len() == 7 {
+        let first = NaiveDate::from_ymd_opt(a as i32, b, 1)?;
+        return first.checked_add_months(Months::new(1))?.pred_opt();
+    }
+    // 2 月 29 日は次のうるう年まで探す
+    (today.year()..=today.year() + 8)
+        .filter_map(|y| NaiveDate::from_ymd_opt(y, a, b))
+        .find(|&d| d >= today)
+}
+
+/// 比較用に正規化した文字列 (`fold_case` なら大文字・小文字を区別しない)
+pub fn normalize(s: &str, fold_case: bool) -> String {
+    if fold_case { s.to_lowercase() } else { s.to_string() }
+}
+
+/// `http://` / `https://` で始まる URL か
+pub fn is_url(w: &str) -> bool {
+    w.starts_with("http://") || w.starts_with("https://")
+}
+
+/// `key:value` 形式のタグか
+/// (key は英字始まりの英数字，value は空でなく `:` を含まず `/` で始まらない．
+/// `"` で囲まれた value は何を含んでもよい)
+fn is_key_value(w: &str) -> bool {
+    let Some((key, value)) = w.split_once(':') else { return false };
+    key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && key.chars().all(|c| c.is_ascii_alphanumeric())
+        && !value.is_empty()
+        && (value.starts_with('"') || (!value.contains(':') && !value.starts_with('/')))
+}
+
+/// 入力中の `due:` がすべて解釈できるか確かめる (`add` / `edit` 用)
+fn check_due(input: &str) -> Result<(), String> {
+    match tokenize(input).iter().filter_map(|(_, w)| w.strip_prefix("due:")).find(|d| parse_due(d).is_none()) {
+        Some(bad) => Err(format!("Invalid due date: {}", bad)),
+        None => Ok(()),
+    }
 }
 
 impl Todo {
     /// パース
     pub fn parse(line: &str) -> Self {
-        let tokens: Vec<&str> = line.trim().split_whitespace().collect();
+        let spans = tokenize(line);
+        let tokens: Vec<&str> = spans.iter().map(|&(_, w)| w).collect();
         let mut idx = 0;
         let mut completion = false;
         let mut priority = None;
@@ -36,41 +250,82 @@ impl Todo {
             completion = true;
             idx += 1;
         }
-        // 優先度
-        if let Some(&tok) = tokens.get(idx) {
-            if tok.len() == 3 && tok.starts_with('(') && tok.ends_with(')') {
-                priority = tok.chars().nth(1);
-                idx += 1;
-            }
+        // 優先度 (英字 1 文字．小文字は大文字に揃える)
+        if let Some(p) = tokens.get(idx)
+            .and_then(|tok| tok.strip_prefix('(')?.strip_suffix(')'))
+            .filter(|p| p.len() == 1 && p.starts_with(|c: char| c.is_ascii_alphabetic()))
+        {
+            priority = p.chars().next().map(|c| c.to_ascii_uppercase());
+            idx += 1;
+        }
+        // 優先度の後ろの完了マーカー (`(A) x 2024-01-01 ...` と書くツール向け．本文の `x` と区別するため日付が続く場合だけ)
+        if !completion
+            && priority.is_some()
+            && tokens.get(idx) == Some(&"x")
+            && tokens.get(idx + 1).is_some_and(|t| NaiveDate::parse_from_str(t, "%Y-%m-%d").is_ok())
+        {
+            completion = true;
+            idx += 1;
         }
         // 日付 (完了 or 作成)
         let d1 = tokens.get(idx).and_then(|t| NaiveDate::parse_from_str(t, "%Y-%m-%d").ok());
         let d2 = tokens.get(idx+1).and_then(|t| NaiveDate::parse_from_str(t, "%Y-%m-%d").ok());
         match (completion, d1, d2) {
             (true, Some(cd), Some(cr)) => { completion_date = Some(cd); creation_date = Some(cr); idx += 2; }
+            (true, Some(cd), None)     => { completion_date = Some(cd); idx += 1; }
             (false, Some(cr), _)       => { creation_date = Some(cr); idx += 1; }
             _ => {}
         }
         // 内容 + タグ + due
-        let mut content = Vec::new();
-        let mut project = None;
-        let mut context = None;
-        let mut supplement = None;
+        let mut content = String::new();
+        let mut last_content = None;
+        let mut projects = Vec::new();
+        let mut contexts = Vec::new();
+        let mut supplement = Vec::new();
         let mut due = None;
-        for &w in &tokens[idx..] {
-            if w.starts_with('+') {
-                project = Some(w[1..].to_string());
-            } else if w.starts_with('@') {
-                context = Some(w[1..].to_string());
-            } else if w.starts_with("due:") {
-                if let Ok(d) = NaiveDate::parse_from_str(&w[4..], "%Y-%m-%d") {
-                    due = Some(d);
+        let mut due_time = None;
+        let mut hidden = false;
+        // 省略した期日は日付に直して保存する (元の行のままにしない)
+        let mut partial_due = false;
+        // 本文の語が元の行で隣り合っていれば間の空白をそのまま残す
+        let mut push_content = |n: usize, start: usize, w: &str| {
+            if !content.is_empty() {
+                match last_content {
+                    Some(prev) if prev + 1 == n => {
+                        let (ps, pw): (usize, &str) = spans[prev];
+                        content.push_str(&line[ps + pw.len()..start]);
+                    }
+                    _ => content.push(' '),
                 }
-                supplement = Some(w.to_string());
-            } else if supplement.is_none() && (w.contains(':') || w.contains('=')) {
-                supplement = Some(w.to_string());
+            }
+            content.push_str(w);
+            last_content = Some(n);
+        };
+        for (n, &(start, w)) in spans.iter().enumerate().skip(idx) {
+            if is_url(w) {
+                // URL は `:` を含むがタグではなく本文の一部
+                push_content(n, start, w);
+            } else if let Some(p) = w.strip_prefix('+') {
+                projects.push(unquote(p).to_string());
+            } else if let Some(c) = w.strip_prefix('@') {
+                contexts.push(unquote(c).to_string());
+            } else if let Some((d, t)) = w.strip_prefix("due:").and_then(parse_due) {
+                due = Some(d);
+                due_time = t;
+                partial_due = w.strip_prefix("due:").and_then(parse_full_due).is_none();
+            } else if let Some(p) = w.strip_prefix("pri:").filter(|p| p.len() == 1 && p.starts_with(|c: char| c.is_ascii_alphabetic())) {
+                // `pri:A` 形式の優先度 (`(A)` があればそちらを使い，使わなかった `pri:` はタグとして残す)
+                match priority {
+                    Some(_) => supplement.push(w.to_string()),
+                    None => priority = p.chars().next().map(|c| c.to_ascii_uppercase()),
+                }
+            } else if w == "h:1" {
+                hidden = true;
+            } else if w.starts_with("due:") || is_key_value(w) {
+                // 解釈できない due もタグとしてそのまま残す
+                supplement.push(w.to_string());
             } else {
-                content.push(w.to_string());
+                push_content(n, start, w);
             }
         }
         Self {
@@ -78,7 +333,9 @@ impl Todo {
             priority,
             completion_date,
             creation_date,
-            description: Description { content: content.join(" "), project, context, supplement, due },
+            description: Description { content, projects, contexts, supplement, due, due_time, hidden },
+            raw: (!partial_due).then(|| line.to_string()),
+            annotations: Vec::new(),
         }
     }
 
@@ -87,17 +344,46 @@ impl Todo {
         let mut parts = Vec::new();
         if self.completion { parts.push("x".into()); }
         if let Some(p) = self.priority { parts.push(format!("({})", p)); }
-        if self.completion {
-            if let Some(cd) = self.completion_date { parts.push(cd.format("%Y-%m-%d").to_string()); }
+        if self.completion && let Some(cd) = self.completion_date {
+            parts.push(cd.format("%Y-%m-%d").to_string());
         }
         if let Some(cr) = self.creation_date { parts.push(cr.format("%Y-%m-%d").to_string()); }
-        parts.push(self.description.content.clone());
-        if let Some(proj) = &self.description.project { parts.push(format!("+{}", proj)); }
-        if let Some(ctx) = &self.description.context { parts.push(format!("@{}", ctx)); }
-        if let Some(sup) = &self.description.supplement { parts.push(sup.clone()); }
+        if !self.description.content.is_empty() { parts.push(self.description.content.clone()); }
+        parts.extend(self.description.projects.iter().map(|p| format!("+{}", quote(p))));
+        parts.extend(self.description.contexts.iter().map(|c| format!("@{}", quote(c))));
+        parts.extend(self.description.supplement.iter().cloned());
+        if self.description.hidden { parts.push("h:1".into()); }
+        if let Some(d) = self.description.due_string() { parts.push(format!("due:{}", d)); }
         parts.join(" ")
     }
 
+    /// `+project` / `@context` 形式のタグを持つか
+    pub fn has_tag(&self, tag: &str, fold_case: bool) -> bool {
+        let (list, name) = if let Some(name) = tag.strip_prefix('+') {
+            (&self.description.projects, name)
+        } else if let Some(name) = tag.strip_prefix('@') {
+            (&self.description.contexts, name)
+        } else {
+            return false;
+        };
+        let name = normalize(name, fold_case);
+        list.iter().any(|t| normalize(t, fold_case) == name)
+    }
+
+    /// 書式化して読み直しても同じ内容になるか (元の行と注釈は比べない)
+    pub fn round_trips(&self) -> bool {
+        let reparsed = Self::parse(&self.format());
+        Self { raw: None, annotations: Vec::new(), ..reparsed } == Self { raw: None, annotations: Vec::new(), ..self.clone() }
+    }
+
+    /// ファイルに書き出す行 (読み込んでから変更が無ければ元の行をそのまま使う)
+    pub fn to_line(&self) -> String {
+        match &self.raw {
+            Some(raw) if Self { annotations: self.annotations.clone(), ..Self::parse(raw) } == *self => raw.clone(),
+            _ => self.format(),
+        }
+    }
+
     /// 完了マーク (今日の日付)
     pub fn mark_done(&mut self) {
         if !self.completion {
@@ -106,38 +392,556 @@ impl Todo {
         }
     }
 
-    /// `add` 用パーサ
-    pub fn from_add(input: &str) -> Result<Self, String> {
+    /// `other` をこのタスクにまとめる (本文をつなげ，タグを合わせ，作成日は早い方，期日は近い方，優先度は高い方)
+    pub fn merge(&mut self, other: &Todo) {
+        let (d, o) = (&mut self.description, &other.description);
+        if !o.content.is_empty() {
+            if !d.content.is_empty() { d.content.push(' '); }
+            d.content.push_str(&o.content);
+        }
+        for p in &o.projects {
+            if !d.projects.contains(p) { d.projects.push(p.clone()); }
+        }
+        for c in &o.contexts {
+            if !d.contexts.contains(c) { d.contexts.push(c.clone()); }
+        }
+        // 同じキーのタグ (`id:` など) はこちらを残す
+        for tag in &o.supplement {
+            let key = tag.split(':').next().unwrap_or_default();
+            if d.tag(key).is_none() { d.supplement.push(tag.clone()); }
+        }
+        if o.due.is_some() && (d.due.is_none() || (o.due, o.due_time) < (d.due, d.due_time)) {
+            (d.due, d.due_time) = (o.due, o.due_time);
+        }
+        d.hidden |= o.hidden;
+        self.creation_date = match (self.creation_date, other.creation_date) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.priority = match (self.priority, other.priority) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.annotations.extend(other.annotations.iter().cloned());
+    }
+
+    /// 完了 / 未完了を切り替え (未完了に戻すときは完了日も消す)
+    pub fn toggle_done(&mut self) {
+        if self.completion {
+            self.completion = false;
+            self.completion_date = None;
+        } else {
+            self.mark_done();
+        }
+    }
+
+    /// `add` 用パーサ (優先度が無い未完了のタスクには `default_priority` を付ける)
+    pub fn from_add(input: &str, default_priority: Option<char>) -> Result<Self, String> {
+        check_due(input)?;
+        // `!N` (N 日後) / `!today` は期日に変換して本文から取り除く
+        let today = Local::now().date_naive();
+        let (mut cleaned, mut last, mut prev_end, mut shorthand) = (String::new(), 0, 0, None);
+        for (start, w) in tokenize(input) {
+            if let Some(days) = w.strip_prefix('!').and_then(|n| if n == "today" { Some(0) } else { n.parse().ok() }) {
+                cleaned.push_str(&input[last..prev_end]);
+                last = start + w.len();
+                shorthand = Some(today.checked_add_days(Days::new(days)).ok_or(format!("Invalid due date: {}", w))?);
+            }
+            prev_end = start + w.len();
+        }
+        cleaned.push_str(&input[last..]);
+        let mut t = Self::parse(&cleaned);
+        if shorthand.is_some() {
+            t.description.due = shorthand;
+            t.description.due_time = None;
+        }
+        if t.description.content.trim().is_empty() {
+            return Err("Task must include non-empty description".into());
+        }
+        if t.creation_date.is_none() {
+            t.creation_date = Some(today);
+        }
+        if t.priority.is_none() && !t.completion {
+            t.priority = default_priority;
+        }
+        Ok(t)
+    }
+
+    /// 既存タスクの編集用パーサ (作成日が無ければ元の作成日を引き継ぐ)
+    pub fn from_edit(input: &str, original: &Todo) -> Result<Self, String> {
+        check_due(input)?;
         let mut t = Self::parse(input);
         if t.description.content.trim().is_empty() {
             return Err("Task must include non-empty description".into());
         }
         if t.creation_date.is_none() {
-            t.creation_date = Some(Local::now().date_naive());
+            t.creation_date = original.creation_date;
         }
+        t.annotations = original.annotations.clone();
         Ok(t)
     }
 
+    /// 重複判定用の正規化文字列 (完了状態・完了日・作成日・id は無視．`loose` なら優先度と期日も無視)
+    pub fn duplicate_key(&self, loose: bool) -> String {
+        let mut bare = Self {
+            completion: false,
+            priority: if loose { None } else { self.priority },
+            completion_date: None,
+            creation_date: None,
+            description: self.description.clone(),
+            raw: None,
+            annotations: Vec::new(),
+        };
+        bare.description.set_tag("id", None);
+        if loose {
+            bare.description.due = None;
+            bare.description.due_time = None;
+        }
+        bare.format()
+    }
+
+    /// 本文中の最初の URL
+    pub fn first_url(&self) -> Option<&str> {
+        self.description.content.split_whitespace().find(|w| is_url(w))
+    }
+
+    /// `note:` タグのメモ (囲みの `"` は外す)
+    pub fn note(&self) -> Option<&str> {
+        self.description.tag("note").map(unquote)
+    }
+
+    /// メモを設定 (`None` なら削除)
+    pub fn set_note(&mut self, note: Option<&str>) {
+        let value = note.map(|n| quote(&n.replace('"', "'")));
+        self.description.set_tag("note", value.as_deref());
+    }
+
+    /// `id:` タグの値
+    pub fn id(&self) -> Option<u64> {
+        self.description.tag("id")?.parse().ok()
+    }
+
     /// 未完了タスクの due 日取得
     pub fn due_uncompleted(&self) -> Option<NaiveDate> {
         if !self.completion { self.description.due } else { None }
     }
 
+    /// 未完了タスクの期日を今日からの相対表現で取得
+    pub fn due_relative(&self, today: NaiveDate) -> Option<String> {
+        let days = (self.due_uncompleted()? - today).num_days();
+        let unit = |n: i64| if n == 1 { "day" } else { "days" };
+        Some(match days {
+            0 => "today".into(),
+            d if d > 0 => format!("in {} {}", d, unit(d)),
+            d => format!("overdue {} {}", -d, unit(-d)),
+        })
+    }
+
+    /// 未完了タスクの期日の区分 (期日が無ければ `None`)
+    pub fn bucket(&self, today: NaiveDate) -> Option<Bucket> {
+        let days = (self.due_uncompleted()? - today).num_days();
+        Some(match days {
+            d if d < 0 => Bucket::Overdue,
+            0 => Bucket::Today,
+            1..=7 => Bucket::ThisWeek,
+            _ => Bucket::Later,
+        })
+    }
+
+    /// 優先度を `delta` 文字ずらす (負なら A 寄り．A〜Z に収め，優先度が無ければ C にする)
+    pub fn bump_priority(&mut self, delta: i8) {
+        self.priority = Some(match self.priority {
+            Some(p) => (p as i8 + delta).clamp(b'A' as i8, b'Z' as i8) as u8 as char,
+            None => 'C',
+        });
+    }
+
+    /// `rec:` タグ (`rec:1w` / `rec:+3d` など．単位は d / w / m / y) から次に作られる期日
+    /// `+` 付きなら今の期日 (無ければ今日) から，そうでなければ今日から数える
+    pub fn next_occurrence(&self, today: NaiveDate) -> Option<NaiveDate> {
+        let rule = self.description.tag("rec")?;
+        let (strict, rule) = match rule.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, rule),
+        };
+        let base = if strict { self.description.due.unwrap_or(today) } else { today };
+        Offset::parse(rule)?.add_to(base)
+    }
+
+    /// `smart` の並び順の点数 (小さいほど急ぐ)
+    /// 期日までの日数 × `due_weight` と優先度の段数 (A = 0．無ければ Z の次) × `priority_weight` の和．
+    /// 期日が無ければ `NO_DUE_DAYS` 日後とみなす
+    pub fn smart_score(&self, today: NaiveDate, due_weight: i64, priority_weight: i64) -> i64 {
+        const NO_DUE_DAYS: i64 = 30;
+        let days = self.description.due.map_or(NO_DUE_DAYS, |d| (d - today).num_days());
+        let rank = self.priority.map_or(26, |p| (p as u8 - b'A') as i64);
+        days * due_weight + rank * priority_weight
+    }
+
+    /// 作成日から今日までの日数 (作成日が無ければ `None`)
+    pub fn age(&self, today: NaiveDate) -> Option<i64> {
+        Some((today - self.creation_date?).num_days())
+    }
+
+    /// 詳細表示用の `(項目名, 値)` の一覧 (値の無い項目は除く．日付は `date_format` で書式化)
+    pub fn details(&self, today: NaiveDate, date_format: &str) -> Vec<(&'static str, String)> {
+        let date = |d: NaiveDate| d.format(date_format).to_string();
+        let d = &self.description;
+        let mut fields = vec![
+            ("Task", d.content.clone()),
+            ("Status", if self.completion { "done".into() } else { "open".into() }),
+        ];
+        if let Some(p) = self.priority { fields.push(("Priority", p.to_string())); }
+        if let Some(cr) = self.creation_date {
+            let age = self.age(today).map(|a| format!(" ({}d ago)", a)).unwrap_or_default();
+            fields.push(("Created", format!("{}{}", date(cr), age)));
+        }
+        if let Some(cd) = self.completion_date { fields.push(("Completed", date(cd))); }
+        if let Some(due) = d.due {
+            let time = d.due_time.map(|t| t.format(" %H:%M").to_string()).unwrap_or_default();
+            let rel = self.due_relative(today).map(|r| format!(" ({})", r)).unwrap_or_default();
+            fields.push(("Due", format!("{}{}{}", date(due), time, rel)));
+        }
+        if let Some(next) = self.next_occurrence(today) { fields.push(("Next", date(next))); }
+        if !d.projects.is_empty() {
+            fields.push(("Projects", d.projects.iter().map(|p| format!("+{}", p)).collect::<Vec<_>>().join(" ")));
+        }
+        if !d.contexts.is_empty() {
+            fields.push(("Contexts", d.contexts.iter().map(|c| format!("@{}", c)).collect::<Vec<_>>().join(" ")));
+        }
+        if let Some(note) = self.note() { fields.push(("Note", note.to_string())); }
+        let tags: Vec<&str> = d.supplement.iter().map(String::as_str).filter(|t| !t.starts_with("note:")).collect();
+        if !tags.is_empty() { fields.push(("Tags", tags.join(" "))); }
+        if d.hidden { fields.push(("Hidden", "yes".into())); }
+        fields.extend(self.annotations.iter().map(|a| ("Annotation", a.trim().to_string())));
+        fields
+    }
+
     // 未完了タスクの priority 取得
     pub fn priority_uncompleted(&self) -> Option<char> {
         if !self.completion { self.priority } else { None }
     }
 
-    /// マーカー色判定
-    pub fn marker_color(&self, today: NaiveDate) -> Color {
-        use ratatui::style::Color;
+    /// マーカー表示用の期日の近さ判定
+    pub fn urgency(&self, today: NaiveDate) -> Urgency {
         if let Some(due) = self.description.due {
             let days = (due - today).num_days();
-            if days <= 3 { Color::Red }
-            else if days <= 7 { Color::Yellow }
-            else { Color::Green }
+            if days < 0 && !self.completion { Urgency::Overdue }
+            else if days <= 3 { Urgency::Soon }
+            else if days <= 7 { Urgency::Week }
+            else { Urgency::Later }
         } else {
-            Color::Gray
+            Urgency::NoDue
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn quoted_project_keeps_spaces() {
+        let t = Todo::parse("call +\"Big Project\" @\"at home\"");
+        assert_eq!(t.description.content, "call");
+        assert_eq!(t.description.projects, ["Big Project"]);
+        assert_eq!(t.description.contexts, ["at home"]);
+        assert_eq!(t.format(), "call +\"Big Project\" @\"at home\"");
+    }
+
+    #[test]
+    fn quoted_key_value_is_one_tag() {
+        let t = Todo::parse("call note:\"see email\"");
+        assert_eq!(t.description.content, "call");
+        assert_eq!(t.description.tag("note"), Some("\"see email\""));
+        assert!(t.round_trips());
+    }
+
+    #[test]
+    fn unmatched_quote_is_literal() {
+        let t = Todo::parse("buy 12\" pipe +hardware due:2024-01-01");
+        assert_eq!(t.description.content, "buy 12\" pipe");
+        assert_eq!(t.description.projects, ["hardware"]);
+        assert_eq!(t.description.due, NaiveDate::from_ymd_opt(2024, 1, 1));
+        assert!(t.round_trips());
+    }
+
+    #[test]
+    fn edit_rejects_invalid_due() {
+        let original = Todo::parse("2024-01-01 buy milk");
+        assert_eq!(Todo::from_edit("buy milk due:2024-13-40", &original).unwrap_err(), "Invalid due date: 2024-13-40");
+        let t = Todo::from_edit("buy milk due:2024-12-24", &original).unwrap();
+        assert_eq!(t.description.due, NaiveDate::from_ymd_opt(2024, 12, 24));
+        assert_eq!(t.creation_date, original.creation_date);
+    }
+
+    #[test]
+    fn quote_inside_word_does_not_group() {
+        let t = Todo::parse("say he\"llo wor\"ld +p");
+        assert_eq!(t.description.content, "say he\"llo wor\"ld");
+        assert_eq!(t.description.projects, ["p"]);
+    }
+
+    #[test]
+    fn due_relative_past_today_and_future() {
+        let t = Todo::parse("a due:2024-06-15");
+        assert_eq!(t.due_relative(date(2024, 6, 17)).as_deref(), Some("overdue 2 days"));
+        assert_eq!(t.due_relative(date(2024, 6, 16)).as_deref(), Some("overdue 1 day"));
+        assert_eq!(t.due_relative(date(2024, 6, 15)).as_deref(), Some("today"));
+        assert_eq!(t.due_relative(date(2024, 6, 12)).as_deref(), Some("in 3 days"));
+        assert_eq!(Todo::parse("x a due:2024-06-15").due_relative(date(2024, 6, 12)), None);
+        assert_eq!(Todo::parse("a").due_relative(date(2024, 6, 12)), None);
+    }
+
+    #[test]
+    fn key_value_detection() {
+        assert!(!is_key_value("2:1"));
+        assert!(!is_key_value("http://x"));
+        assert!(is_key_value("ref:ABC"));
+        assert!(!is_key_value("ref:"));
+        assert!(is_key_value("note:\"a: b\""));
+        let t = Todo::parse("ratio 2:1 ref:ABC");
+        assert_eq!(t.description.content, "ratio 2:1");
+        assert_eq!(t.description.supplement, ["ref:ABC"]);
+    }
+
+    #[test]
+    fn url_stays_in_content_through_round_trip() {
+        let line = "read https://example.com/a?b=1 +web";
+        let t = Todo::parse(line);
+        assert_eq!(t.description.content, "read https://example.com/a?b=1");
+        assert!(t.description.supplement.is_empty());
+        assert_eq!(t.format(), line);
+        assert_eq!(Todo::parse(&t.format()), Todo { raw: Some(line.into()), ..t });
+    }
+
+    #[test]
+    fn from_add_validates_due() {
+        let t = Todo::from_add("pay rent due:2024-07-01", None).unwrap();
+        assert_eq!(t.description.due, Some(date(2024, 7, 1)));
+        let t = Todo::from_add("call due:2024-07-01T09:30", None).unwrap();
+        assert_eq!(t.description.due_time, NaiveTime::from_hms_opt(9, 30, 0));
+        assert_eq!(Todo::from_add("pay rent due:2024-02-30", None).unwrap_err(), "Invalid due date: 2024-02-30");
+        assert_eq!(Todo::from_add("pay rent due:soon", None).unwrap_err(), "Invalid due date: soon");
+    }
+
+    #[test]
+    fn doubled_spaces_in_content_round_trip() {
+        let t = Todo::parse("(A) table  1  vs  2 +games due:2024-06-01");
+        assert_eq!(t.description.content, "table  1  vs  2");
+        assert_eq!(t.description.projects, ["games"]);
+        assert_eq!(t.format(), "(A) table  1  vs  2 +games due:2024-06-01");
+        assert!(t.round_trips());
+    }
+
+    /// 書式の違いを含むタスクの一覧 (読み直しで構造が変わらないことを確かめる用)
+    const FIXTURE: &[&str] = &[
+        "(A) 2024-01-02 call mom +family @phone due:2024-06-01",
+        "x 2024-05-01 2024-04-01 pay rent +home rec:1m",
+        "x (B) 2024-05-02 filed taxes",
+        "buy milk ref:ABC note:\"see email\" id:12",
+        "table  1  vs  2 due:2024-06-01T09:30",
+        "read https://example.com/x?y=1 +web h:1",
+        "meet +\"Big Project\" @\"at home\" t:2024-06-01",
+        "sort 12\" pipe +hardware due:2024-13-40",
+    ];
+
+    #[test]
+    fn whole_list_survives_format_and_parse() {
+        let todos: Vec<Todo> = FIXTURE.iter().map(|l| Todo::parse(l)).collect();
+        let reparsed: Vec<Todo> = todos.iter().map(|t| Todo::parse(&t.format())).collect();
+        let bare = |ts: &[Todo]| ts.iter().map(|t| Todo { raw: None, ..t.clone() }).collect::<Vec<_>>();
+        assert_eq!(bare(&reparsed), bare(&todos));
+        assert!(todos.iter().all(Todo::round_trips));
+    }
+
+    #[test]
+    fn first_url_finds_the_first_link() {
+        let t = Todo::parse("compare http://a.example and https://b.example +web");
+        assert_eq!(t.first_url(), Some("http://a.example"));
+        assert_eq!(Todo::parse("no link here ftp://x").first_url(), None);
+    }
+
+    #[test]
+    fn set_note_and_read_it_back() {
+        let mut t = Todo::parse("call bob");
+        t.set_note(Some("ask about \"the\" invoice"));
+        assert_eq!(t.note(), Some("ask about 'the' invoice"));
+        assert_eq!(t.format(), "call bob note:\"ask about 'the' invoice\"");
+        assert_eq!(Todo::parse(&t.format()).note(), t.note());
+        t.set_note(Some("short"));
+        assert_eq!(t.format(), "call bob note:short");
+        t.set_note(None);
+        assert_eq!(t.note(), None);
+        assert_eq!(t.format(), "call bob");
+    }
+
+    #[test]
+    fn lowercase_priority_is_normalized() {
+        let t = Todo::parse("(a) lower");
+        assert_eq!(t.priority, Some('A'));
+        assert_eq!(t.format(), "(A) lower");
+        let t = Todo::parse("(1) digit");
+        assert_eq!(t.priority, None);
+        assert_eq!(t.description.content, "(1) digit");
+    }
+
+    #[test]
+    fn due_with_and_without_time_round_trips() {
+        for (line, time) in [("call due:2024-07-01", None), ("call due:2024-07-01T09:30", NaiveTime::from_hms_opt(9, 30, 0))] {
+            let mut t = Todo::parse(line);
+            assert_eq!((t.description.due, t.description.due_time), (Some(date(2024, 7, 1)), time));
+            t.raw = None;
+            assert_eq!(t.format(), line);
+            assert_eq!(Todo { raw: None, ..Todo::parse(&t.format()) }, t);
+        }
+    }
+
+    #[test]
+    fn age_counts_days_since_creation() {
+        let today = date(2024, 3, 1);
+        assert_eq!(Todo::parse("2024-02-20 a").age(today), Some(10));
+        assert_eq!(Todo::parse("2024-03-01 a").age(today), Some(0));
+        assert_eq!(Todo::parse("a").age(today), None);
+    }
+
+    #[test]
+    fn bucket_follows_days_until_due() {
+        let today = date(2024, 3, 1);
+        let bucket = |line: &str| Todo::parse(line).bucket(today);
+        assert_eq!(bucket("a due:2024-02-29"), Some(Bucket::Overdue));
+        assert_eq!(bucket("a due:2024-03-01"), Some(Bucket::Today));
+        assert_eq!(bucket("a due:2024-03-02"), Some(Bucket::ThisWeek));
+        assert_eq!(bucket("a due:2024-03-08"), Some(Bucket::ThisWeek));
+        assert_eq!(bucket("a due:2024-03-09"), Some(Bucket::Later));
+        assert_eq!(bucket("x a due:2024-02-29"), None);
+        assert_eq!(bucket("a"), None);
+    }
+
+    #[test]
+    fn bump_priority_stays_within_a_to_z() {
+        let bumped = |line: &str, delta| {
+            let mut t = Todo::parse(line);
+            t.bump_priority(delta);
+            t.priority
+        };
+        assert_eq!(bumped("(B) a", -1), Some('A'));
+        assert_eq!(bumped("(A) a", -1), Some('A'));
+        assert_eq!(bumped("(B) a", 1), Some('C'));
+        assert_eq!(bumped("(Z) a", 1), Some('Z'));
+        assert_eq!(bumped("a", -1), Some('C'));
+    }
+
+    #[test]
+    fn bang_shorthand_sets_due_and_leaves_content() {
+        let today = Local::now().date_naive();
+        let t = Todo::from_add("call mom !0", None).unwrap();
+        assert_eq!((t.description.due, t.description.content.as_str()), (Some(today), "call mom"));
+        let t = Todo::from_add("pay !3 rent +home", None).unwrap();
+        assert_eq!(t.description.due, Some(today + chrono::Duration::days(3)));
+        assert_eq!(t.description.content, "pay rent");
+        assert!(!t.format().contains('!'));
+        let t = Todo::from_add("wow! !today", None).unwrap();
+        assert_eq!((t.description.due, t.description.content.as_str()), (Some(today), "wow!"));
+    }
+
+    #[test]
+    fn details_lists_only_present_fields() {
+        let mut t = Todo::parse("(A) 2024-02-25 call mom +family @phone note:\"after lunch\" id:4 due:2024-03-03T09:30");
+        t.annotations.push("  bring the list".into());
+        let today = date(2024, 3, 1);
+        assert_eq!(t.details(today, "%d/%m/%Y"), [
+            ("Task", "call mom".to_string()),
+            ("Status", "open".into()),
+            ("Priority", "A".into()),
+            ("Created", "25/02/2024 (5d ago)".into()),
+            ("Due", "03/03/2024 09:30 (in 2 days)".into()),
+            ("Projects", "+family".into()),
+            ("Contexts", "@phone".into()),
+            ("Note", "after lunch".into()),
+            ("Tags", "id:4".into()),
+            ("Annotation", "bring the list".into()),
+        ]);
+        let done = Todo::parse("x 2024-03-01 tidy h:1");
+        assert_eq!(done.details(today, "%Y-%m-%d"), [
+            ("Task", "tidy".to_string()),
+            ("Status", "done".into()),
+            ("Completed", "2024-03-01".into()),
+            ("Hidden", "yes".into()),
+        ]);
+    }
+
+    #[test]
+    fn completion_marker_after_priority_is_accepted() {
+        let canonical = Todo::parse("x (A) 2024-03-02 2024-03-01 ship it");
+        let swapped = Todo::parse("(A) x 2024-03-02 2024-03-01 ship it");
+        assert_eq!(Todo { raw: None, ..swapped.clone() }, Todo { raw: None, ..canonical.clone() });
+        assert!(swapped.completion);
+        assert_eq!(swapped.completion_date, Some(date(2024, 3, 2)));
+        assert_eq!(swapped.format(), "x (A) 2024-03-02 2024-03-01 ship it");
+        // 日付が続かない `x` は本文
+        let t = Todo::parse("(A) x marks the spot");
+        assert!(!t.completion);
+        assert_eq!(t.description.content, "x marks the spot");
+    }
+
+    #[test]
+    fn next_occurrence_by_unit() {
+        let today = date(2024, 1, 31);
+        let next = |line: &str| Todo::parse(line).next_occurrence(today);
+        assert_eq!(next("a rec:1d"), Some(date(2024, 2, 1)));
+        assert_eq!(next("a rec:2w"), Some(date(2024, 2, 14)));
+        assert_eq!(next("a rec:1m"), Some(date(2024, 2, 29)));
+        assert_eq!(next("a rec:1y"), Some(date(2025, 1, 31)));
+        // `+` 付きは期日から数える
+        assert_eq!(next("a rec:+1w due:2024-01-10"), Some(date(2024, 1, 17)));
+        assert_eq!(next("a rec:1w due:2024-01-10"), Some(date(2024, 2, 7)));
+        assert_eq!(next("a rec:often"), None);
+        assert_eq!(next("a"), None);
+    }
+
+    #[test]
+    fn partial_dates_resolve_from_today() {
+        let today = date(2024, 6, 15);
+        assert_eq!(resolve_partial_date("2024-02", today), Some(date(2024, 2, 29)));
+        assert_eq!(resolve_partial_date("2025-12", today), Some(date(2025, 12, 31)));
+        assert_eq!(resolve_partial_date("06-15", today), Some(date(2024, 6, 15)));
+        assert_eq!(resolve_partial_date("06-14", today), Some(date(2025, 6, 14)));
+        assert_eq!(resolve_partial_date("02-29", today), Some(date(2028, 2, 29)));
+        assert_eq!(resolve_partial_date("2024-13", today), None);
+        assert_eq!(resolve_partial_date("6-1", today), None);
+        assert_eq!(resolve_partial_date("2024-06-01", today), None);
+    }
+
+    #[test]
+    fn merge_combines_two_tasks() {
+        let mut a = Todo::parse("(B) 2024-02-01 write report +work id:1 due:2024-03-10");
+        let mut b = Todo::parse("(A) 2024-01-15 add charts +work +q1 @desk id:2 est:2h h:1 due:2024-03-05");
+        b.annotations.push("  use last year's data".into());
+        a.merge(&b);
+        assert_eq!(a.description.content, "write report add charts");
+        assert_eq!(a.description.projects, ["work", "q1"]);
+        assert_eq!(a.description.contexts, ["desk"]);
+        assert_eq!(a.description.supplement, ["id:1", "est:2h"]);
+        assert_eq!(a.description.due, Some(date(2024, 3, 5)));
+        assert!(a.description.hidden);
+        assert_eq!((a.priority, a.creation_date), (Some('A'), Some(date(2024, 1, 15))));
+        assert_eq!(a.annotations, ["  use last year's data"]);
+    }
+
+    #[test]
+    fn pri_tag_sets_priority_unless_parenthesized() {
+        let t = Todo::parse("call mom pri:a");
+        assert_eq!((t.priority, t.description.content.as_str()), (Some('A'), "call mom"));
+        assert!(t.description.supplement.is_empty());
+        let t = Todo::parse("(B) call mom pri:A");
+        assert_eq!((t.priority, t.description.supplement.as_slice()), (Some('B'), ["pri:A".to_string()].as_slice()));
+        assert_eq!(Todo { raw: None, ..t }.format(), "(B) call mom pri:A");
+        let t = Todo::parse("call mom pri:high");
+        assert_eq!((t.priority, t.description.supplement.as_slice()), (None, ["pri:high".to_string()].as_slice()));
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+/// テストごとの空の作業ディレクトリ
+fn work_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("todo-cli-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// `dir` で `todo <args>` を実行
+fn todo(dir: &PathBuf, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_todo")).args(args).current_dir(dir).output().unwrap()
+}
+
+#[test]
+fn add_writes_the_task_and_exits_zero() {
+    let dir = work_dir("add");
+    let out = todo(&dir, &["add", "water plants due:2024-06-01"]);
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    let txt = fs::read_to_string(dir.join("todo.txt")).unwrap();
+    assert_eq!(txt.lines().count(), 1);
+    assert!(txt.contains("water plants") && txt.contains("due:2024-06-01"), "{}", txt);
+}
+
+#[test]
+fn errors_exit_non_zero() {
+    let dir = work_dir("error");
+    let out = todo(&dir, &["add", "milk due:2024-13-40"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&out.stderr).trim(), "Invalid due date: 2024-13-40");
+    assert!(!dir.join("todo.txt").exists());
+    assert_eq!(todo(&dir, &["nonsense"]).status.code(), Some(1));
+}
+
+#[test]
+fn init_creates_an_empty_file_once() {
+    let dir = work_dir("init");
+    let out = todo(&dir, &["--init"]);
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "Created todo.txt");
+    assert_eq!(fs::read_to_string(dir.join("todo.txt")).unwrap(), "");
+    fs::write(dir.join("todo.txt"), "keep me\n").unwrap();
+    let out = todo(&dir, &["--init"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "todo.txt already exists");
+    assert_eq!(fs::read_to_string(dir.join("todo.txt")).unwrap(), "keep me\n");
+}